@@ -0,0 +1,54 @@
+//! Instructions per second of the interpreter, running the bundled ROMs at a
+//! high tickrate, like XO-CHIP games and fast-forward do.
+//!
+//! To compare a change, run `cargo +stable bench -- --save-baseline before`
+//! first, then `cargo +stable bench -- --baseline before` with the change.
+
+use chippie::chip8::blocks::Engine;
+use chippie::chip8::Cpu;
+use chippie::testing::bundled_roms;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const FRAMES: usize = 10;
+
+const TICKS_PER_FRAME: u16 = 1000;
+
+/// Runs a ROM from the start on `engine`, and returns the instructions it ran
+fn run(engine: Engine, rom: &[u8]) -> u64 {
+    let mut cpu = Cpu::new();
+    cpu.engine = engine;
+    cpu.seed_rng(0);
+    cpu.bus.load_rom(rom);
+    (0..FRAMES)
+        .map(|_| cpu.partial_frame(TICKS_PER_FRAME, u32::MAX) as u64)
+        .sum()
+}
+
+fn bench_roms(c: &mut Criterion) {
+    let roms = bundled_roms();
+    let instructions = roms
+        .iter()
+        .map(|(_, rom)| run(Engine::Interpreter, rom))
+        .sum();
+
+    let mut group = c.benchmark_group("roms");
+    group.throughput(Throughput::Elements(instructions));
+    group.bench_function("all", |b| {
+        b.iter(|| {
+            roms.iter()
+                .map(|(_, rom)| run(Engine::Interpreter, rom))
+                .sum::<u64>()
+        })
+    });
+    group.bench_function("blocks", |b| {
+        b.iter(|| {
+            roms.iter()
+                .map(|(_, rom)| run(Engine::Blocks, rom))
+                .sum::<u64>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_roms);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+//! Runs arbitrary ROMs with arbitrary keys, quirks and profiles, and checks
+//! that the interpreter neither panics nor leaves its memory or stack.
+//!
+//! Run with `cargo fuzz run run_rom`. Crashes go to `artifacts/run_rom/`;
+//! once fixed, add the ROM as a test in `tests/fuzz_regressions.rs`.
+#![no_main]
+
+use chippie::chip8::cpu::StackOverflow;
+use chippie::chip8::profile::PROFILES;
+use chippie::chip8::quirks::Quirks;
+use chippie::chip8::timing::Timing;
+use chippie::chip8::Cpu;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+/// More frames find little more, and slow the fuzzer down
+const MAX_FRAMES: usize = 60;
+
+const TICKS_PER_FRAME: u16 = 20;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    profile: u8,
+    /// One bit per quirk
+    quirks: u16,
+    vip_timing: bool,
+    stack_overflow: u8,
+    seed: u64,
+    /// The keys held down in each frame, one bit per key
+    frames: Vec<u16>,
+    rom: Vec<u8>,
+}
+
+fn quirks(bits: u16) -> Quirks {
+    let bit = |n: u16| bits & (1 << n) != 0;
+    Quirks {
+        shift: bit(0),
+        memory_increment_by_x: bit(1),
+        memory_leave_i_unchanged: bit(2),
+        jump: bit(3),
+        logic: bit(4),
+        vblank: bit(5),
+        wrap: bit(6),
+        row_collisions: bit(7),
+        key_wait_press: bit(8),
+        big_sprites: bit(9),
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let profile = PROFILES[input.profile as usize % PROFILES.len()];
+    let mut cpu = Cpu::with_profile(profile);
+    cpu.quirks = quirks(input.quirks);
+    if input.vip_timing {
+        cpu.timing = Timing::VipCycles;
+    }
+    cpu.stack_overflow = match input.stack_overflow % 3 {
+        0 => StackOverflow::Wrap,
+        1 => StackOverflow::Error,
+        _ => StackOverflow::Memory,
+    };
+    cpu.seed_rng(input.seed);
+    cpu.bus.load_rom(&input.rom);
+
+    for &keys in input.frames.iter().take(MAX_FRAMES) {
+        for (key, key_down) in cpu.keys_down.iter_mut().enumerate() {
+            *key_down = keys & (1 << key) != 0;
+        }
+        cpu.ticks(TICKS_PER_FRAME);
+
+        assert!((cpu.pc as usize) < cpu.bus.memory().len(), "PC {:04X} out of memory", cpu.pc);
+        // the stack in memory has no limit but the memory's
+        if cpu.stack_overflow != StackOverflow::Memory {
+            assert!(cpu.stack().len() <= cpu.stack_depth, "stack of {}", cpu.stack().len());
+        }
+    }
+});
@@ -1,74 +1,176 @@
-use crate::{chip8, keys, roms_db};
+use crate::{chip8, keys, rom_analyzer, roms_db, touch};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{rom_scanner, symbols};
 use egui::{
     menu, Color32, ColorImage, Context, ImageData, Pos2, RichText, TextureOptions, Vec2, Vec2b,
 };
 use once_cell::sync::Lazy;
-use sha1::{Digest, Sha1};
 use std::sync::Arc;
 use web_time::{Duration, Instant};
 
 // Constants
-const EMU_ASPECT_RATIO: f32 = 64_f32 / 32_f32;
+const KEYMAPS_KEY: &str = "keymaps";
 static FRAME_DURATION: Lazy<Duration> = Lazy::new(|| Duration::from_secs_f64(1_f64 / 60_f64));
-
-fn calculate_sha1(data: &[u8]) -> String {
-    let mut hasher = Sha1::new();
-    hasher.update(data);
-    hex::encode(hasher.finalize())
-}
+/// Frames run at most to catch up after a stall, like a tab in the background
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+/// How much longer frames last in slow motion
+const SLOW_MOTION_FACTOR: u32 = 4;
+/// Why the interpreter's debugging views are off while the VIP runs
+const NOT_ON_VIP: &str = "The emulated COSMAC VIP runs its own interpreter, which can't be watched";
+/// Why the stack depth is off while the stack is kept in memory
+const STACK_IN_MEMORY: &str = "A stack in memory grows until it writes over the memory below it";
 
 pub struct TemplateApp<'a> {
     paused: bool,
-    ticks_per_frame: u16,
+    /// Paces instructions and timers by the time passed
+    clock: chip8::clock::Clock,
+    /// Whether the fast-forward key is held
+    fast_forward: bool,
+    /// Frames run per frame while fast-forwarding
+    fast_forward_speed: u32,
+    slow_motion: bool,
+    /// A single frame to run while paused
+    advance_frame: bool,
     updates: u32,
     begin_updates_time: Instant,
     frames: u32,
     begin_time: Instant,
-    next_update: Instant,
-    color_on: Color32,
-    color_off: Color32,
+    last_update: Instant,
+    palette: roms_db::Palette,
+    font: chip8::font::FontSet,
+    profile: chip8::profile::MachineProfile,
+    /// The machine picked from the Platform menu, over the one the ROM asks for
+    profile_override: Option<chip8::profile::MachineProfile>,
+    /// The font picked from the Font menu, over the ROM's and the machine's
+    font_override: Option<chip8::font::FontSet>,
+    timing: chip8::timing::Timing,
+    engine: chip8::blocks::Engine,
+    /// Sounds a click when the program gets a key from FX0A
+    key_click: bool,
+    /// Overrides the profile's stack depth
+    stack_depth: Option<usize>,
+    stack_overflow: chip8::cpu::StackOverflow,
+    quirks: chip8::quirks::Quirks,
+    /// Quirks set in the Quirks menu, over the ROM's
+    quirks_override: Option<chip8::quirks::Quirks>,
+    rotation_override: Option<u16>,
     image_texture: Option<egui::TextureHandle>,
     chip8: chip8::Cpu,
+    /// Platform whose quirks a shadow run compares against the current ones
+    shadow_platform: Option<&'static roms_db::Platform>,
+    shadow: Option<chip8::shadow::Shadow>,
+    rom_data: Vec<u8>,
     keys: keys::KeyMapper,
+    keymaps: keys::KeymapSettings,
+    show_keymap: bool,
+    /// Watches for quirk-sensitive instructions while the report is shown
+    show_quirk_report: bool,
+    show_call_stack: bool,
+    /// Names for the addresses of the loaded ROM
+    #[cfg(not(target_arch = "wasm32"))]
+    symbols: Option<symbols::Symbols>,
+    rebinding: Option<usize>,
+    touch: touch::TouchInput,
     hash: Option<String>,
     program_info: Option<&'a roms_db::Program>,
     rom_info: Option<&'a roms_db::Rom>,
+    /// Guessed platform, for ROMs that aren't in the database
+    analysis: Option<rom_analyzer::Analysis>,
     show_popup: bool,
     start_clicked: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    rom_index: Option<rom_scanner::RomIndex>,
+    /// Image of the original CHIP-8 interpreter, to run on an emulated VIP
+    #[cfg(not(target_arch = "wasm32"))]
+    vip_interpreter: Option<Vec<u8>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    use_vip: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    vip: Option<chip8::vip::Vip>,
 }
 
 impl Default for TemplateApp<'_> {
     fn default() -> Self {
         Self {
             paused: true,
-            ticks_per_frame: 10,
+            clock: chip8::clock::Clock::default(),
+            fast_forward: false,
+            fast_forward_speed: 4,
+            slow_motion: false,
+            advance_frame: false,
             updates: 0,
             begin_updates_time: Instant::now(),
             frames: 0,
             begin_time: Instant::now(),
-            next_update: Instant::now() + *FRAME_DURATION,
-            color_on: Color32::WHITE,
-            color_off: Color32::BLACK,
+            last_update: Instant::now(),
+            palette: roms_db::Palette::default(),
+            font: chip8::font::FontSet::default(),
+            profile: chip8::profile::MachineProfile::default(),
+            profile_override: None,
+            font_override: None,
+            timing: chip8::timing::Timing::Instructions,
+            engine: chip8::blocks::Engine::Interpreter,
+            key_click: false,
+            stack_depth: None,
+            stack_overflow: chip8::cpu::StackOverflow::Wrap,
+            quirks: chip8::quirks::Quirks::default(),
+            quirks_override: None,
+            rotation_override: None,
             image_texture: None,
             chip8: chip8::Cpu::new(),
+            shadow_platform: None,
+            shadow: None,
+            rom_data: Vec::new(),
             keys: keys::KeyMapper::new(None),
+            keymaps: keys::KeymapSettings::default(),
+            show_keymap: false,
+            show_quirk_report: false,
+            show_call_stack: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            symbols: None,
+            rebinding: None,
+            touch: touch::TouchInput::new(touch::TouchMode::None),
             hash: None,
             program_info: None,
             rom_info: None,
+            analysis: None,
             show_popup: false,
             start_clicked: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            rom_index: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            vip_interpreter: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            use_vip: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            vip: None,
         }
     }
 }
 
 impl TemplateApp<'_> {
     /// Called once before the first frame.
-    pub fn new(_: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app: Self = Default::default();
+
+        // Load the user's keymaps from the previous session
+        if let Some(storage) = cc.storage {
+            if let Some(keymaps) = eframe::get_value(storage, KEYMAPS_KEY) {
+                app.keymaps = keymaps;
+                app.keys.key_map = app.keymaps.default_for(app.profile.keypad);
+            }
+        }
+
+        app
     }
 }
 
 impl eframe::App for TemplateApp<'_> {
+    /// Called by the framework to save state before shutdown, and every now and then.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, KEYMAPS_KEY, &self.keymaps);
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         ctx.input(|x| {
@@ -85,11 +187,24 @@ impl eframe::App for TemplateApp<'_> {
             self.show_stats_bar(ctx, ui);
         });
 
+        if self.touch.mode.has_keypad() {
+            egui::TopBottomPanel::bottom("keypad").show(ctx, |ui| {
+                self.touch.show_keypad(ui, &self.keys.controls);
+            });
+        }
+
+        // the background changes color while the buzzer sounds
+        let mut emu_frame = egui::Frame::none().inner_margin(0.0);
+        let beeping = self.machine().is_beeping();
+        if let Some(background) = self.palette.background(beeping) {
+            emu_frame = emu_frame.fill(background);
+        }
+
         egui::CentralPanel::default()
-            .frame(egui::Frame::none().inner_margin(0.0))
+            .frame(emu_frame)
             .show(ctx, |ui| {
                 self.show_emu(ctx, ui);
-                ctx.request_repaint_after(self.next_update - Instant::now());
+                ctx.request_repaint_after(self.until_update());
             });
 
         // Show the popup window when `show_popup` is true
@@ -103,33 +218,125 @@ impl eframe::App for TemplateApp<'_> {
                 self.start_clicked = false;
             }
         }
+
+        if self.show_keymap {
+            self.show_keymap_window(ctx);
+        }
+
+        if self.show_quirk_report {
+            self.show_quirk_report_window(ctx);
+        }
+
+        if self.show_call_stack {
+            self.show_call_stack_window(ctx);
+        }
     }
 }
 
 impl TemplateApp<'_> {
     fn proc_input(&mut self, _ctx: &Context, x: &egui::InputState) {
+        // the next key pressed is bound to the CHIP-8 key being edited
+        if let Some(chip8_key) = self.rebinding {
+            let pressed = x.events.iter().find_map(|event| match event {
+                egui::Event::Key {
+                    key, pressed: true, ..
+                } => Some(*key),
+                _ => None,
+            });
+            match pressed {
+                Some(egui::Key::Escape) => self.rebinding = None,
+                Some(key) => {
+                    self.keys.key_map[chip8_key] = key;
+                    self.rebinding = None;
+                }
+                None => (),
+            }
+            self.machine().set_keys([false; 16]);
+            return;
+        }
+
         // SPACE runs/pauses the emu
         if x.key_released(egui::Key::Space) {
             self.paused = !self.paused;
         }
-        // register keys down
-        for i in 0..self.chip8.keys_down.len() {
-            self.chip8.keys_down[i] = x.key_down(self.keys.key_map[i]);
+        // TAB fast-forwards while held, PERIOD runs a frame while paused
+        self.fast_forward = x.key_down(egui::Key::Tab);
+        if x.key_pressed(egui::Key::Period) {
+            self.advance_frame = true;
         }
+        // register keys down, from the keyboard and the touch screen
+        self.touch.proc_input(x);
+        let mut keys_down = self.keys.keys_down(x);
+        for (key_down, touched) in keys_down.iter_mut().zip(self.touch.keys_down) {
+            *key_down |= touched;
+        }
+        self.machine().set_keys(keys_down);
     }
 
     fn update_emu_state(&mut self) {
         // doing an update(s)
         let now = Instant::now();
-        while self.next_update < now {
-            if !self.paused {
-                self.chip8.ticks(self.ticks_per_frame);
-                self.updates += 1;
-            } else {
-                self.begin_updates_time += *FRAME_DURATION;
-            }
+        let passed = now - self.last_update;
+        self.last_update = now;
 
-            self.next_update += *FRAME_DURATION;
+        // the time lost beyond a few frames is skipped rather than caught up on
+        let elapsed = passed.min(*FRAME_DURATION * MAX_CATCH_UP_FRAMES);
+        self.begin_updates_time += passed - elapsed;
+
+        if std::mem::take(&mut self.advance_frame) && self.paused {
+            self.run_tick();
+        }
+        if self.paused {
+            self.begin_updates_time += elapsed;
+            return;
+        }
+        let time = if self.fast_forward {
+            elapsed * self.fast_forward_speed
+        } else if self.slow_motion {
+            elapsed / SLOW_MOTION_FACTOR
+        } else {
+            elapsed
+        };
+        for _ in 0..self.clock.advance(time) {
+            self.run_tick();
+        }
+    }
+
+    /// Runs the instructions due before the next timer tick, and the tick
+    fn run_tick(&mut self) {
+        let ticks = self.clock.tick_instructions();
+        match &mut self.shadow {
+            Some(shadow) => shadow.run_frame(&mut self.chip8, ticks),
+            None => self.machine().run_frame(ticks),
+        }
+        self.updates += 1;
+    }
+
+    /// Real time until the next timer tick is due
+    fn until_update(&self) -> Duration {
+        let until_tick = self.clock.until_tick();
+        if self.fast_forward {
+            until_tick / self.fast_forward_speed
+        } else if self.slow_motion {
+            until_tick * SLOW_MOTION_FACTOR
+        } else {
+            until_tick
+        }
+    }
+
+    fn speed_menu(&mut self, ui: &mut egui::Ui) {
+        ui.label("Fast-forward while TAB is held:");
+        ui.add(egui::Slider::new(&mut self.fast_forward_speed, 2..=16).suffix("x"));
+        ui.checkbox(
+            &mut self.slow_motion,
+            format!("Slow motion (1/{} speed)", SLOW_MOTION_FACTOR),
+        );
+        ui.separator();
+        if ui
+            .add_enabled(self.paused, egui::Button::new("Advance frame (.)"))
+            .clicked()
+        {
+            self.advance_frame = true;
         }
     }
 
@@ -145,41 +352,356 @@ impl TemplateApp<'_> {
         // Display each item in the menu
         for &filename in filenames {
             if ui.button(filename).clicked() {
-                self.hash = None;
-                self.program_info = None;
-                self.rom_info = None;
-
-                // load ROM data
-                let bindata = roms.get(filename).unwrap();
-                let hash = calculate_sha1(bindata);
-
-                // get program and rom info, and set tickrate
-                if let Some(id) = roms_db::HASHES.get(&hash) {
-                    self.program_info = roms_db::PROGRAMS.get(*id as usize);
-                    self.rom_info = self
-                        .program_info
-                        .and_then(|pr_info| pr_info.roms.get(&hash));
-                    if let Some(ticks) = self.rom_info.and_then(|rinfo| rinfo.get_tickrate()) {
-                        self.ticks_per_frame = ticks;
+                self.load_rom(roms.get(filename).unwrap());
+                ui.close_menu();
+            }
+        }
+    }
+
+    fn load_rom(&mut self, bindata: &[u8]) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.symbols = None;
+        }
+        self.program_info = None;
+        self.rom_info = None;
+        self.analysis = None;
+
+        let hash = roms_db::calculate_sha1(bindata);
+        self.hash = Some(hash.clone());
+        // nothing carries over from the previous ROM
+        self.palette = roms_db::Palette::default();
+        self.touch.mode = touch::TouchMode::None;
+
+        // get program and rom info, and set tickrate
+        if let Some(id) = roms_db::HASHES.get(&hash) {
+            self.program_info = roms_db::PROGRAMS.get(*id as usize);
+            self.rom_info = self
+                .program_info
+                .and_then(|pr_info| pr_info.roms.get(&hash));
+            if let Some(ticks) = self.rom_info.and_then(|rinfo| rinfo.get_tickrate()) {
+                self.clock.instruction_rate = ticks as u32 * chip8::clock::TIMER_RATE;
+            }
+            if let Some(palette) = self.rom_info.and_then(|rinfo| rinfo.get_palette()) {
+                self.palette = palette;
+            }
+            self.keys.controls = self
+                .rom_info
+                .map(|rinfo| rinfo.get_controls())
+                .unwrap_or_default();
+            if let Some(mode) = self
+                .rom_info
+                .and_then(|rinfo| rinfo.get_touch_input_mode())
+                .and_then(touch::TouchMode::from_db)
+            {
+                self.touch.mode = mode;
+            }
+
+            // show popup next frame
+            self.show_popup = true;
+            self.start_clicked = false;
+            self.paused = true;
+        } else {
+            self.keys.controls = Vec::new();
+            self.paused = false;
+
+            // guess the platform from the code itself. Unless one was picked,
+            // the machine is part of the guess; the rest load programs at 200.
+            let start = self.profile_override.unwrap_or_default().program_start;
+            self.analysis = Some(rom_analyzer::analyze(bindata, start));
+        }
+        self.profile = self.profile_override.unwrap_or_else(|| self.rom_profile());
+        self.quirks = self.quirks_override.unwrap_or_else(|| self.rom_quirks());
+        self.font = self.pick_font();
+
+        self.keys.key_map = self.keymaps.key_map_for(Some(&hash), self.profile.keypad);
+        self.rom_data = bindata.to_vec();
+        self.reset();
+    }
+
+    /// Restarts the loaded ROM on a new emu, with the current memory layout and font
+    fn reset(&mut self) {
+        self.chip8 = self.new_cpu(self.quirks);
+        if self.show_quirk_report {
+            self.chip8.quirk_watch = Some(chip8::quirk_watch::QuirkWatch::default());
+        }
+        self.touch.keypad = self.profile.keypad;
+        self.shadow = None;
+        if let Some(platform) = self.shadow_platform {
+            let quirks = chip8::quirks::Quirks::for_platform(platform.get_id(), &platform.quirks);
+            let cpu = self.new_cpu(quirks);
+            self.shadow = Some(chip8::shadow::Shadow::new(
+                &mut self.chip8,
+                cpu,
+                rand::random(),
+            ));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.vip = match &self.vip_interpreter {
+                Some(interpreter) if self.use_vip => {
+                    Some(chip8::vip::Vip::new(interpreter, &self.rom_data))
+                }
+                _ => None,
+            };
+            // the shadow runs beside the interpreter, not the VIP
+            if self.vip.is_some() {
+                self.shadow = None;
+            }
+        }
+    }
+
+    /// A machine with the loaded ROM and the current settings
+    fn new_cpu(&self, quirks: chip8::quirks::Quirks) -> chip8::Cpu {
+        let mut cpu = chip8::Cpu::with_profile(self.profile);
+        cpu.timing = self.timing;
+        cpu.engine = self.engine;
+        cpu.key_click = self.key_click;
+        if let Some(depth) = self.stack_depth {
+            cpu.stack_depth = depth;
+        }
+        cpu.stack_overflow = self.stack_overflow;
+        cpu.quirks = quirks;
+        cpu.bus.load_font(self.font);
+        cpu.bus.load_rom(&self.rom_data);
+        cpu
+    }
+
+    /// The machine that runs the ROM: the emulated VIP if enabled, or the interpreter
+    fn machine(&mut self) -> &mut dyn chip8::Machine {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(vip) = &mut self.vip {
+            return vip;
+        }
+        &mut self.chip8
+    }
+
+    /// Whether the ROM runs on the emulated VIP, so `chip8` sits idle
+    fn on_vip(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.vip.is_some() {
+            return true;
+        }
+        false
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn vip_menu(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        if ui.button("COSMAC VIP interpreter...").clicked() {
+            ui.close_menu();
+            if let Some(file) = rfd::FileDialog::new().pick_file() {
+                match std::fs::read(&file) {
+                    Ok(interpreter) => {
+                        self.vip_interpreter = Some(interpreter);
+                        self.use_vip = true;
+                        self.reset();
                     }
-                    self.hash = Some(hash);
+                    Err(e) => log::error!("Error loading {}: {}", file.display(), e),
+                }
+            }
+        }
+        if ui
+            .add_enabled(
+                self.vip_interpreter.is_some(),
+                egui::Checkbox::new(&mut self.use_vip, "Run on an emulated COSMAC VIP"),
+            )
+            .clicked()
+        {
+            // switching machines starts the ROM over
+            self.reset();
+            ui.close_menu();
+        }
+    }
 
-                    // show popup next frame
-                    self.show_popup = true;
-                    self.start_clicked = false;
-                    self.paused = true;
-                } else {
-                    self.paused = false;
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rom_folder_menu(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Scan folder...").clicked() {
+            ui.close_menu();
+            if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                match rom_scanner::RomIndex::open(&folder) {
+                    Ok(index) => self.rom_index = Some(index),
+                    Err(e) => log::error!("Error scanning {}: {}", folder.display(), e),
                 }
+            }
+        }
 
-                // create new emu with the same colors
-                self.chip8 = chip8::cpu::Cpu::new();
-                self.chip8.bus.load_rom(bindata);
-                ui.close_menu();
+        let Some(index) = &mut self.rom_index else {
+            return;
+        };
+
+        if ui.button("Refresh").clicked() {
+            ui.close_menu();
+            if let Err(e) = index.update() {
+                log::error!("Error refreshing {}: {}", index.root.display(), e);
+            }
+        }
+        if ui.button("Export index...").clicked() {
+            ui.close_menu();
+            if let Some(file) = rfd::FileDialog::new()
+                .set_file_name("rom-index.json")
+                .add_filter("JSON", &["json"])
+                .save_file()
+            {
+                let result = index
+                    .export_json()
+                    .map_err(std::io::Error::from)
+                    .and_then(|json_data| std::fs::write(&file, json_data));
+                if let Err(e) = result {
+                    log::error!("Error exporting {}: {}", file.display(), e);
+                }
+            }
+        }
+
+        // at-a-glance coverage of the collection
+        ui.separator();
+        let matched = index.matched();
+        let unknown = index.unknown();
+        ui.label(format!(
+            "{} files, {} titles, {} unknown, {} duplicated",
+            index.entries.len(),
+            matched.len(),
+            unknown.len(),
+            index.duplicates().len()
+        ));
+
+        let mut selected = None;
+        ui.menu_button("Matched titles", |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for title in &matched {
+                    for entry in &title.files {
+                        let text = if title.files.len() > 1 {
+                            format!("{} ({})", title.title, entry.path)
+                        } else {
+                            title.title.to_string()
+                        };
+                        if ui.button(text).clicked() {
+                            selected = Some(index.full_path(entry));
+                        }
+                    }
+                }
+            });
+        });
+        ui.menu_button("Unknown files", |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for entry in &unknown {
+                    if ui.button(&entry.path).clicked() {
+                        selected = Some(index.full_path(entry));
+                    }
+                }
+            });
+        });
+
+        if let Some(file) = selected {
+            ui.close_menu();
+            match std::fs::read(&file) {
+                Ok(bindata) => self.load_rom(&bindata),
+                Err(e) => log::error!("Error loading {}: {}", file.display(), e),
+            }
+        }
+    }
+
+    fn set_palette(&mut self, palette: roms_db::Palette) {
+        self.palette = palette;
+        // redraw the framebuffer texture with the new colors
+        self.machine().gpu().has_changed = true;
+    }
+
+    /// The first platform the loaded ROM was made for, from the ROM database
+    fn rom_platform(&self) -> Option<&'static roms_db::Platform> {
+        self.rom_info
+            .and_then(|rinfo| rinfo.get_platform_ids().first())
+            .and_then(|platform| roms_db::get_platform(platform))
+    }
+
+    /// The machine the loaded ROM was made for: from the ROM database, or
+    /// guessed from its code
+    fn rom_profile(&self) -> chip8::profile::MachineProfile {
+        let platform = self
+            .rom_info
+            .and_then(|rinfo| rinfo.get_platform_ids().first())
+            .map(String::as_str)
+            .or_else(|| self.analysis.as_ref().map(|analysis| analysis.platform));
+        platform.map_or_else(
+            chip8::profile::MachineProfile::default,
+            chip8::profile::MachineProfile::for_platform,
+        )
+    }
+
+    /// The quirks of the platform the loaded ROM was made for: from the ROM
+    /// database, or guessed from its code
+    fn rom_quirks(&self) -> chip8::quirks::Quirks {
+        match (self.rom_platform(), &self.analysis) {
+            (Some(platform), _) => {
+                chip8::quirks::Quirks::for_platform(platform.get_id(), &platform.quirks)
             }
+            (None, Some(analysis)) => analysis.quirks,
+            (None, None) => chip8::quirks::Quirks::default(),
+        }
+    }
+
+    /// Sets the quirks, or with None goes back to the ROM's
+    fn set_quirks(&mut self, quirks: Option<chip8::quirks::Quirks>) {
+        self.quirks_override = quirks;
+        self.quirks = quirks.unwrap_or_else(|| self.rom_quirks());
+        self.chip8.quirks = self.quirks;
+    }
+
+    /// The font the ROM database gives the loaded ROM
+    fn rom_font(&self) -> Option<chip8::font::FontSet> {
+        self.rom_info
+            .and_then(|rinfo| rinfo.get_font_style())
+            .and_then(chip8::font::FontSet::from_style)
+    }
+
+    /// The font picked from the Font menu, or else the ROM's, or else the
+    /// machine's
+    fn pick_font(&self) -> chip8::font::FontSet {
+        self.font_override
+            .or_else(|| self.rom_font())
+            .unwrap_or(self.profile.font)
+    }
+
+    /// Switches to another machine, or with None to the one the ROM was made
+    /// for, with its own keypad and font unless one was picked; the memory
+    /// layout changes, so the ROM starts over
+    fn set_profile(&mut self, profile: Option<chip8::profile::MachineProfile>) {
+        self.profile_override = profile;
+        self.profile = profile.unwrap_or_else(|| self.rom_profile());
+        self.font = self.pick_font();
+        self.keys.key_map = self
+            .keymaps
+            .key_map_for(self.hash.as_deref(), self.profile.keypad);
+        self.reset();
+    }
+
+    /// Switches to a font, or with None back to the ROM's or the machine's
+    fn set_font(&mut self, font: Option<chip8::font::FontSet>) {
+        self.font_override = font;
+        self.font = self.pick_font();
+        self.chip8.bus.load_font(self.font);
+        if let Some(shadow) = &mut self.shadow {
+            shadow.cpu.bus.load_font(self.font);
         }
     }
 
+    /// Switches to a stack depth, or with None back to the platform's
+    fn set_stack_depth(&mut self, depth: Option<usize>) {
+        self.stack_depth = depth;
+        let depth = depth.unwrap_or(self.profile.stack_depth);
+        self.chip8.stack_depth = depth;
+        if let Some(shadow) = &mut self.shadow {
+            shadow.cpu.stack_depth = depth;
+        }
+    }
+
+    fn set_rotation(&mut self, rotation: Option<u16>) {
+        self.rotation_override = rotation;
+        // redraw the framebuffer texture with the new orientation
+        self.machine().gpu().has_changed = true;
+    }
+
     fn show_menu(&mut self, _ctx: &Context, ui: &mut egui::Ui) {
         menu::bar(ui, |ui| {
             ui.menu_button("Programs", |ui| {
@@ -187,33 +709,252 @@ impl TemplateApp<'_> {
                     self.load_roms_menu(ui, &roms_db::ROMS)
                 });
                 ui.menu_button("Games", |ui| self.load_roms_menu(ui, &roms_db::ROMS2));
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("ROM folder", |ui| self.rom_folder_menu(ui));
             });
 
-            ui.menu_button("Color", |ui| {
-                /* if ui.butto n("From ROM (if any)").clicked() {
-                    if let Some(info) = self.program_info {
-                        if let Some(ticks) = info.roms[&hash].colors() {
-                            self.ticks_per_frame = ticks;
+            ui.menu_button("Platform", |ui| {
+                let label = format!("From ROM ({})", self.rom_profile().name);
+                if ui.radio(self.profile_override.is_none(), label).clicked() {
+                    self.set_profile(None);
+                    ui.close_menu();
+                }
+                for profile in chip8::profile::PROFILES {
+                    if ui
+                        .radio(self.profile_override == Some(profile), profile.name)
+                        .clicked()
+                    {
+                        self.set_profile(Some(profile));
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                let mut vip_timing = self.timing == chip8::timing::Timing::VipCycles;
+                if ui
+                    .checkbox(&mut vip_timing, "COSMAC VIP cycle timing")
+                    .clicked()
+                {
+                    self.timing = if vip_timing {
+                        chip8::timing::Timing::VipCycles
+                    } else {
+                        chip8::timing::Timing::Instructions
+                    };
+                    self.chip8.timing = self.timing;
+                    ui.close_menu();
+                }
+                let mut blocks = self.engine == chip8::blocks::Engine::Blocks;
+                if ui
+                    .add_enabled(
+                        self.timing == chip8::timing::Timing::Instructions,
+                        egui::Checkbox::new(&mut blocks, "Compile blocks (faster)"),
+                    )
+                    .clicked()
+                {
+                    self.engine = if blocks {
+                        chip8::blocks::Engine::Blocks
+                    } else {
+                        chip8::blocks::Engine::Interpreter
+                    };
+                    self.chip8.engine = self.engine;
+                    if let Some(shadow) = &mut self.shadow {
+                        shadow.cpu.engine = self.engine;
+                    }
+                    ui.close_menu();
+                }
+                ui.menu_button("Stack", |ui| self.stack_menu(ui));
+                #[cfg(not(target_arch = "wasm32"))]
+                self.vip_menu(ui);
+            });
+
+            ui.menu_button("Quirks", |ui| {
+                let platform = self.rom_platform().map(|platform| platform.get_name());
+                let detected = self.analysis.as_ref().map(|analysis| {
+                    roms_db::get_platform(analysis.platform)
+                        .map_or(analysis.platform, |platform| platform.get_name())
+                });
+                let label = match platform.or(detected) {
+                    Some(name) => format!("From ROM ({})", name),
+                    None => "From ROM".to_string(),
+                };
+                if ui.radio(self.quirks_override.is_none(), label).clicked() {
+                    self.set_quirks(None);
+                    ui.close_menu();
+                }
+                ui.separator();
+                let quirks = [
+                    (&mut self.quirks.shift, "Shifts change VX in place"),
+                    (
+                        &mut self.quirks.memory_increment_by_x,
+                        "FX55/FX65 add X to I",
+                    ),
+                    (
+                        &mut self.quirks.memory_leave_i_unchanged,
+                        "FX55/FX65 leave I unchanged",
+                    ),
+                    (&mut self.quirks.jump, "BXNN jumps to XNN + VX"),
+                    (&mut self.quirks.logic, "Logic ops reset VF"),
+                    (&mut self.quirks.vblank, "Display wait (vblank)"),
+                    (&mut self.quirks.wrap, "Sprites wrap around the screen"),
+                    (
+                        &mut self.quirks.row_collisions,
+                        "Hires VF counts rows (SUPER-CHIP)",
+                    ),
+                    (
+                        &mut self.quirks.key_wait_press,
+                        "FX0A goes on at key press (SUPER-CHIP)",
+                    ),
+                    (
+                        &mut self.quirks.big_sprites,
+                        "DXY0 draws 16x16 in lores (SUPER-CHIP)",
+                    ),
+                ];
+                let mut changed = false;
+                for (quirk, label) in quirks {
+                    changed |= ui.checkbox(quirk, label).clicked();
+                }
+                if changed {
+                    self.set_quirks(Some(self.quirks));
+                }
+                ui.separator();
+                let on_vip = self.on_vip();
+                ui.add_enabled_ui(!on_vip, |ui| {
+                    ui.menu_button("Compare with", |ui| {
+                        for platform in roms_db::PLATFORMS.iter() {
+                            let selected =
+                                self.shadow_platform.map(|p| p.get_id()) == Some(platform.get_id());
+                            if ui.radio(selected, platform.get_name()).clicked() {
+                                self.shadow_platform = Some(platform);
+                                self.reset();
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        if ui
+                            .add_enabled(
+                                self.shadow_platform.is_some(),
+                                egui::Button::new("Stop comparing"),
+                            )
+                            .clicked()
+                        {
+                            self.shadow_platform = None;
+                            self.shadow = None;
+                            ui.close_menu();
                         }
+                    })
+                    .response
+                    .on_disabled_hover_text(NOT_ON_VIP);
+                });
+                if ui
+                    .add_enabled(!on_vip, egui::Button::new("Quirk report..."))
+                    .on_disabled_hover_text(NOT_ON_VIP)
+                    .clicked()
+                {
+                    self.show_quirk_report = true;
+                    if self.chip8.quirk_watch.is_none() {
+                        self.chip8.quirk_watch = Some(chip8::quirk_watch::QuirkWatch::default());
                     }
-                    if let Some(colors) = self.program_info. {}
-                    // todo!()
-                } */
+                    ui.close_menu();
+                }
+            });
+
+            ui.menu_button("Color", |ui| {
+                let rom_palette = self.rom_info.and_then(|rinfo| rinfo.get_palette());
+                if ui
+                    .add_enabled(rom_palette.is_some(), egui::Button::new("From ROM"))
+                    .clicked()
+                {
+                    self.set_palette(rom_palette.unwrap());
+                    ui.close_menu();
+                }
                 if ui.button("B/W").clicked() {
-                    self.color_on = Color32::WHITE;
-                    self.color_off = Color32::BLACK;
+                    self.set_palette(roms_db::Palette::default());
                     ui.close_menu();
                 }
                 if ui.button("Orange").clicked() {
-                    self.color_on = Color32::from_rgb(0xFF, 0xAA, 0);
-                    self.color_off = Color32::BLACK;
+                    self.set_palette(roms_db::Palette::new(
+                        Color32::BLACK,
+                        Color32::from_rgb(0xFF, 0xAA, 0),
+                    ));
                     ui.close_menu();
                 }
                 if ui.button("Timendus").clicked() {
-                    self.color_on = Color32::from_rgb(0xFF, 0xCC, 0x01);
-                    self.color_off = Color32::from_rgb(0x99, 0x66, 0x01);
+                    self.set_palette(roms_db::Palette::new(
+                        Color32::from_rgb(0x99, 0x66, 0x01),
+                        Color32::from_rgb(0xFF, 0xCC, 0x01),
+                    ));
+                    ui.close_menu();
+                }
+            });
+
+            ui.menu_button("Font", |ui| {
+                if ui.radio(self.font_override.is_none(), "From ROM").clicked() {
+                    self.set_font(None);
                     ui.close_menu();
                 }
+                ui.separator();
+                ui.label("Small font:");
+                for small in chip8::font::SMALL_FONTS {
+                    let selected = self.font_override.is_some_and(|font| font.small == small);
+                    if ui.radio(selected, small.name).clicked() {
+                        self.set_font(Some(chip8::font::FontSet { small, ..self.font }));
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                ui.label("Big font:");
+                for big in chip8::font::BIG_FONTS {
+                    let selected = self.font_override.is_some_and(|font| font.big == big);
+                    if ui.radio(selected, big.name).clicked() {
+                        self.set_font(Some(chip8::font::FontSet { big, ..self.font }));
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            ui.menu_button("Rotation", |ui| {
+                if ui
+                    .radio(self.rotation_override.is_none(), "From ROM")
+                    .clicked()
+                {
+                    self.set_rotation(None);
+                    ui.close_menu();
+                }
+                for rotation in [0, 90, 180, 270] {
+                    if ui
+                        .radio(
+                            self.rotation_override == Some(rotation),
+                            format!("{}°", rotation),
+                        )
+                        .clicked()
+                    {
+                        self.set_rotation(Some(rotation));
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            ui.menu_button("Keys", |ui| {
+                if ui.button("Keymap...").clicked() {
+                    self.show_keymap = true;
+                    ui.close_menu();
+                }
+                if ui
+                    .checkbox(&mut self.key_click, "Click on FX0A key press")
+                    .clicked()
+                {
+                    self.chip8.key_click = self.key_click;
+                    ui.close_menu();
+                }
+                ui.menu_button("On-screen input", |ui| {
+                    for mode in touch::TouchMode::ALL {
+                        if ui
+                            .radio_value(&mut self.touch.mode, mode, mode.name())
+                            .clicked()
+                        {
+                            ui.close_menu();
+                        }
+                    }
+                });
             });
 
             // Create a pause/run toggle button
@@ -230,11 +971,17 @@ impl TemplateApp<'_> {
             {
                 self.paused = !self.paused;
             }
+            ui.menu_button("Speed", |ui| self.speed_menu(ui));
+            if !self.paused && self.fast_forward {
+                ui.label(format!("{}x", self.fast_forward_speed));
+            } else if !self.paused && self.slow_motion {
+                ui.label(format!("1/{}x", SLOW_MOTION_FACTOR));
+            }
 
             // Show sound or not
             ui.separator();
 
-            if self.chip8.sound_timer > 0 {
+            if self.machine().is_beeping() {
                 ui.label(
                     RichText::new("BEEP")
                         .strong()
@@ -247,8 +994,13 @@ impl TemplateApp<'_> {
 
             // Show emu speed slider
             ui.separator();
-            ui.label("Tickrate (speed):");
-            ui.add(egui::Slider::new(&mut self.ticks_per_frame, 1..=256).text("ticks/frame"));
+            ui.label("Speed:");
+            ui.add_enabled(
+                self.timing == chip8::timing::Timing::Instructions,
+                egui::Slider::new(&mut self.clock.instruction_rate, 60..=15360)
+                    .logarithmic(true)
+                    .text("instructions/s"),
+            );
         });
     }
 
@@ -311,6 +1063,7 @@ impl TemplateApp<'_> {
                         };
                         ui.add(egui::Label::new(text));
 
+                        let is_loaded = romhash == self.hash.as_deref().unwrap();
                         egui::Grid::new(format!("rom{}", romhash))
                             .num_columns(2)
                             .show(ui, |ui| {
@@ -330,7 +1083,12 @@ impl TemplateApp<'_> {
                                     ui.label(colors);
                                     ui.end_row();
                                 }
-                                if let Some(keys) = romfile.get_keys() {
+                                let keys = if is_loaded {
+                                    self.keys.describe_controls()
+                                } else {
+                                    romfile.get_keys()
+                                };
+                                if let Some(keys) = keys {
                                     ui.label("Keys:");
                                     ui.label(keys);
                                     ui.end_row();
@@ -376,6 +1134,265 @@ impl TemplateApp<'_> {
             });
     }
 
+    fn show_keymap_window(&mut self, ctx: &Context) {
+        let mut open = true;
+        egui::Window::new("Keymap")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Click a key, then press the keyboard key to bind to it.");
+                ui.add_space(5.0);
+
+                // the hex keypad, as laid out on the machine
+                egui::Grid::new("keypad").show(ui, |ui| {
+                    for row in self.profile.keypad.layout() {
+                        for chip8_key in row {
+                            let text = if self.rebinding == Some(chip8_key) {
+                                format!("{:X}\n...", chip8_key)
+                            } else {
+                                format!("{:X}\n{}", chip8_key, self.keys.key_map[chip8_key].name())
+                            };
+                            let button = egui::Button::new(text)
+                                .min_size(Vec2::new(60.0, 40.0))
+                                .selected(self.rebinding == Some(chip8_key));
+                            if ui.add(button).clicked() {
+                                self.rebinding = Some(chip8_key);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.label("Presets:");
+                    for (name, key_map) in keys::KeyMapper::PRESETS {
+                        if ui.button(name).clicked() {
+                            self.keys.key_map = key_map;
+                            self.rebinding = None;
+                        }
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Save as default").clicked() {
+                        self.keymaps
+                            .set_default(self.profile.keypad, self.keys.key_map);
+                    }
+                    let hash = self.hash.clone();
+                    if let Some(hash) = hash {
+                        if ui.button("Save for this ROM").clicked() {
+                            self.keymaps.per_rom.insert(hash.clone(), self.keys.key_map);
+                        }
+                        let has_rom_keymap = self.keymaps.per_rom.contains_key(&hash);
+                        if ui
+                            .add_enabled(has_rom_keymap, egui::Button::new("Forget for this ROM"))
+                            .clicked()
+                        {
+                            self.keymaps.per_rom.remove(&hash);
+                            self.keys.key_map = self.keymaps.default_for(self.profile.keypad);
+                        }
+                    }
+                });
+            });
+
+        if !open {
+            self.show_keymap = false;
+            self.rebinding = None;
+        }
+    }
+
+    fn show_quirk_report_window(&mut self, ctx: &Context) {
+        let mut open = true;
+        egui::Window::new("Quirk report")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if self.on_vip() {
+                    ui.label(NOT_ON_VIP);
+                    return;
+                }
+                ui.label("Instructions of the running program that depend on a quirk.");
+                ui.add_space(5.0);
+
+                let findings = self
+                    .chip8
+                    .quirk_watch
+                    .as_ref()
+                    .map(|watch| &watch.findings)
+                    .filter(|findings| !findings.is_empty());
+                match findings {
+                    Some(findings) => {
+                        egui::Grid::new("quirk_report")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.strong("Quirk");
+                                ui.strong("Count");
+                                ui.strong("First at");
+                                ui.strong("What differs");
+                                ui.end_row();
+                                for (quirk, finding) in findings {
+                                    ui.label(*quirk);
+                                    ui.label(finding.count.to_string());
+                                    ui.label(format!("{:04X}", finding.first_pc));
+                                    ui.label(&finding.detail);
+                                    ui.end_row();
+                                }
+                            });
+                    }
+                    None => {
+                        ui.label("Nothing quirk-sensitive has run yet.");
+                    }
+                }
+
+                ui.separator();
+                if ui.button("Clear").clicked() {
+                    self.chip8.quirk_watch = Some(chip8::quirk_watch::QuirkWatch::default());
+                }
+            });
+
+        if !open {
+            self.show_quirk_report = false;
+            self.chip8.quirk_watch = None;
+        }
+    }
+
+    fn stack_menu(&mut self, ui: &mut egui::Ui) {
+        // a stack in memory is as deep as the memory below it
+        let in_memory = self.stack_overflow == chip8::cpu::StackOverflow::Memory;
+        let mut depth = self.stack_depth.unwrap_or(self.profile.stack_depth);
+        if ui
+            .add_enabled(
+                !in_memory,
+                egui::Slider::new(&mut depth, 1..=64).text("levels"),
+            )
+            .on_disabled_hover_text(STACK_IN_MEMORY)
+            .changed()
+        {
+            self.set_stack_depth(Some(depth));
+        }
+        if ui
+            .add_enabled(
+                !in_memory && self.stack_depth.is_some(),
+                egui::Button::new(format!("Platform's depth ({})", self.profile.stack_depth)),
+            )
+            .clicked()
+        {
+            self.set_stack_depth(None);
+        }
+        ui.separator();
+        ui.label("When the stack is full:");
+        let overflows = [
+            (chip8::cpu::StackOverflow::Wrap, "Drop the oldest call"),
+            (chip8::cpu::StackOverflow::Error, "Stop with an error"),
+            (
+                chip8::cpu::StackOverflow::Memory,
+                "Keep the stack in memory (COSMAC VIP)",
+            ),
+        ];
+        for (overflow, label) in overflows {
+            if ui
+                .radio_value(&mut self.stack_overflow, overflow, label)
+                .clicked()
+            {
+                // the stack moves in or out of memory, so the ROM starts over
+                self.reset();
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        if ui
+            .add_enabled(!self.on_vip(), egui::Button::new("Call stack..."))
+            .on_disabled_hover_text(NOT_ON_VIP)
+            .clicked()
+        {
+            self.show_call_stack = true;
+            ui.close_menu();
+        }
+    }
+
+    fn show_call_stack_window(&mut self, ctx: &Context) {
+        let mut open = true;
+        egui::Window::new("Call stack")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let describe = |address: u16| {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(name) = self.symbols.as_ref().and_then(|s| s.name(address)) {
+                        return format!("{:04X} {}", address, name);
+                    }
+                    format!("{:04X}", address)
+                };
+
+                if self.on_vip() {
+                    ui.label(NOT_ON_VIP);
+                    return;
+                }
+                if let Some(error) = self.chip8.error() {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+                ui.label(format!("PC is at {}", describe(self.chip8.pc)));
+                let stack = self.chip8.stack();
+                if self.chip8.stack_overflow == chip8::cpu::StackOverflow::Memory {
+                    ui.label(format!("{} levels used", stack.len()));
+                } else {
+                    ui.label(format!(
+                        "{} of {} levels used",
+                        stack.len(),
+                        self.chip8.stack_depth
+                    ));
+                }
+                ui.add_space(5.0);
+
+                if !stack.is_empty() {
+                    egui::Grid::new("call_stack").striped(true).show(ui, |ui| {
+                        ui.strong("Routine");
+                        ui.strong("Called from");
+                        ui.end_row();
+                        // the latest call first
+                        for &return_address in stack.iter().rev() {
+                            let caller = return_address.wrapping_sub(2);
+                            let opcode = u16::from_be_bytes([
+                                self.chip8.bus.read_byte(caller),
+                                self.chip8.bus.read_byte(caller.wrapping_add(1)),
+                            ]);
+                            // the call may have been written over since
+                            if opcode & 0xf000 == 0x2000 {
+                                ui.label(describe(opcode & 0xfff));
+                            } else {
+                                ui.label("?");
+                            }
+                            ui.label(describe(caller));
+                            ui.end_row();
+                        }
+                    });
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.separator();
+                    if ui.button("Load symbols...").clicked() {
+                        if let Some(file) = rfd::FileDialog::new().pick_file() {
+                            let symbols = std::fs::read_to_string(&file)
+                                .map_err(|e| e.to_string())
+                                .and_then(|text| symbols::Symbols::parse(&text));
+                            match symbols {
+                                Ok(symbols) => self.symbols = Some(symbols),
+                                Err(e) => log::error!("Error loading {}: {}", file.display(), e),
+                            }
+                        }
+                    }
+                }
+            });
+
+        if !open {
+            self.show_call_stack = false;
+        }
+    }
+
     fn show_stats_bar(&self, _ctx: &Context, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label(format!(
@@ -388,19 +1405,69 @@ impl TemplateApp<'_> {
                 self.frames,
                 self.frames as f32 / self.begin_time.elapsed().as_secs_f32()
             ));
+
+            if let Some(error) = self.chip8.error().filter(|_| !self.on_vip()) {
+                ui.separator();
+                ui.colored_label(Color32::LIGHT_RED, format!("Stopped: {}", error));
+            }
+            if let Some(analysis) = &self.analysis {
+                ui.separator();
+                let name = roms_db::get_platform(analysis.platform)
+                    .map_or(analysis.platform, |platform| platform.get_name());
+                ui.label(format!(
+                    "Detected: {} ({:.0}%)",
+                    name,
+                    analysis.confidence * 100.0
+                ))
+                .on_hover_text(analysis.evidence.join("\n"));
+            }
+            if let (Some(shadow), Some(platform)) = (&self.shadow, self.shadow_platform) {
+                ui.separator();
+                match &shadow.divergence {
+                    Some(divergence) => {
+                        ui.label(format!(
+                            "Differs from {} in frame {}",
+                            platform.get_name(),
+                            divergence.frame
+                        ))
+                        .on_hover_text(format!(
+                            "After instruction {} of the frame, {:04X} at {:04X}: {}",
+                            divergence.instruction,
+                            divergence.opcode,
+                            divergence.pc,
+                            divergence.detail
+                        ));
+                    }
+                    None => {
+                        ui.label(format!("Same as {} so far", platform.get_name()));
+                    }
+                }
+            }
         });
     }
 
+    /// Clockwise screen rotation in degrees, from the ROM database or overridden
+    fn rotation(&self) -> u16 {
+        self.rotation_override
+            .or_else(|| self.rom_info.and_then(|rinfo| rinfo.get_rotation()))
+            .unwrap_or(0)
+    }
+
     fn show_emu(&mut self, ctx: &Context, ui: &mut egui::Ui) {
+        // Sideways screens swap the aspect ratio
+        let gpu = self.machine().gpu();
+        let emu_aspect_ratio = gpu.width as f32 / gpu.height as f32;
+        let aspect_ratio = match self.rotation() {
+            90 | 270 => 1.0 / emu_aspect_ratio,
+            _ => emu_aspect_ratio,
+        };
+
         // Calculate the available aspect ratio
         let avail_asp_ratio = ui.available_width() / ui.available_height();
 
-        if avail_asp_ratio > EMU_ASPECT_RATIO {
+        if avail_asp_ratio > aspect_ratio {
             // Layout horizontally, add spacer to the left/right
-            let image_size = Vec2::new(
-                EMU_ASPECT_RATIO * ui.available_height(),
-                ui.available_height(),
-            );
+            let image_size = Vec2::new(aspect_ratio * ui.available_height(), ui.available_height());
             let spacer = (ui.available_width() - image_size.x) / 2.0;
 
             ui.horizontal(|ui| {
@@ -409,10 +1476,7 @@ impl TemplateApp<'_> {
             });
         } else {
             // Layout vertically, add spacer to the top/bottom
-            let image_size = Vec2::new(
-                ui.available_width(),
-                ui.available_width() / EMU_ASPECT_RATIO,
-            );
+            let image_size = Vec2::new(ui.available_width(), ui.available_width() / aspect_ratio);
             let spacer = (ui.available_height() - image_size.y) / 2.0;
 
             ui.vertical(|ui| {
@@ -423,50 +1487,82 @@ impl TemplateApp<'_> {
     }
 
     fn show_emu_image(&mut self, ctx: &Context, ui: &mut egui::Ui, image_size: Vec2) {
-        // Load new or update the existing framebuffer texture
-        let image_texture = self.image_texture.get_or_insert_with(|| {
-            self.chip8.bus.gpu.has_changed = false;
-            ctx.load_texture(
-                "gpu",
-                gpu_to_image_data(&self.chip8.bus.gpu.buffer, self.color_on, self.color_off),
-                TextureOptions::NEAREST,
-            )
-        });
+        let rotation = self.rotation();
 
-        // Update the framebuffer texture
-        if self.chip8.bus.gpu.has_changed {
-            self.chip8.bus.gpu.has_changed = false;
-            image_texture.set(
-                gpu_to_image_data(&self.chip8.bus.gpu.buffer, self.color_on, self.color_off),
-                TextureOptions::NEAREST,
-            );
+        // Load new or update the existing framebuffer texture
+        let first_frame = self.image_texture.is_none();
+        let gpu = self.machine().gpu();
+        let changed = std::mem::take(&mut gpu.has_changed);
+        let gpu = (changed || first_frame).then(|| gpu.clone());
+        if let Some(gpu) = gpu {
+            let image = gpu_to_image_data(&gpu, &self.palette, rotation);
+            match &mut self.image_texture {
+                Some(image_texture) => image_texture.set(image, TextureOptions::NEAREST),
+                None => {
+                    self.image_texture =
+                        Some(ctx.load_texture("gpu", image, TextureOptions::NEAREST))
+                }
+            }
         }
+        let image_texture = self.image_texture.as_ref().unwrap();
 
-        // Draw the texture in the UI
-        ui.image((image_texture.id(), image_size));
+        // Draw the texture in the UI, with any touch areas on top
+        let response = ui.image((image_texture.id(), image_size));
+        self.touch.rotation = rotation;
+        self.touch
+            .show_overlay(ui, response.rect, &self.keys.controls);
 
         self.frames += 1;
     }
 }
 
-fn gpu_to_image_data(buffer: &[u64; 32], color_on: Color32, color_off: Color32) -> ImageData {
-    let mut pixel_data: Vec<Color32> = Vec::with_capacity(64 * 32);
-    for buff_line in buffer.iter() {
-        let mut mask = 1 << 63;
-        for _ in 0..64 {
-            pixel_data.push(if buff_line & mask > 0 {
-                color_on
-            } else {
-                color_off
-            });
-            mask >>= 1;
+fn gpu_to_image_data(gpu: &chip8::Gpu, palette: &roms_db::Palette, rotation: u16) -> ImageData {
+    let color_on = palette.color_on();
+    let color_off = palette.color_off();
+    let mut pixel_data: Vec<Color32> = Vec::with_capacity(gpu.width * gpu.height);
+    for y in 0..gpu.height {
+        for x in 0..gpu.width {
+            pixel_data.push(if gpu.pixel(x, y) { color_on } else { color_off });
         }
     }
 
-    let color_image = ColorImage {
-        size: [64, 32],
-        pixels: pixel_data,
-    };
+    let color_image = rotate_image(
+        ColorImage {
+            size: [gpu.width, gpu.height],
+            pixels: pixel_data,
+        },
+        rotation,
+    );
 
     ImageData::Color(Arc::new(color_image))
 }
+
+/// Rotates an image clockwise by 90, 180 or 270 degrees
+fn rotate_image(image: ColorImage, rotation: u16) -> ColorImage {
+    let [width, height] = image.size;
+    let source = |x: usize, y: usize| image.pixels[y * width + x];
+
+    let (size, pixels) = match rotation {
+        90 => (
+            [height, width],
+            (0..width)
+                .flat_map(|y| (0..height).map(move |x| (x, y)))
+                .map(|(x, y)| source(y, height - 1 - x))
+                .collect(),
+        ),
+        180 => (
+            [width, height],
+            image.pixels.iter().rev().copied().collect(),
+        ),
+        270 => (
+            [height, width],
+            (0..width)
+                .flat_map(|y| (0..height).map(move |x| (x, y)))
+                .map(|(x, y)| source(width - 1 - y, x))
+                .collect(),
+        ),
+        _ => return image,
+    };
+
+    ColorImage { size, pixels }
+}
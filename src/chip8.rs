@@ -1,22 +1,71 @@
 #![allow(unused_macros)]
 #![allow(unused_imports)]
 
+/// The `blocks` module compiles straight-line runs of instructions and finds
+/// idle loops, for running programs faster than the interpreter does.
+pub mod blocks;
+
 /// The `bus` module contains the logic and structures for managing the system bus,
 /// which coordinates data flow between the CPU, GPU, and other system components.
 pub mod bus;
 
+/// The `cdp1802` module contains the RCA CDP1802 microprocessor of the
+/// COSMAC VIP, which runs the machine code routines of hybrid programs.
+pub mod cdp1802;
+
+/// The `clock` module paces the instructions and the 60Hz timers by the
+/// time passed, each at a rate of its own.
+pub mod clock;
+
 /// The `cpu` module contains the logic for the Central Processing Unit (CPU),
 /// responsible for executing instructions.
 pub mod cpu;
 
+/// The `font` module contains the hex digit fonts of the various CHIP-8
+/// interpreters, used by the font instructions.
+pub mod font;
+
 /// The `gpu` module contains the logic for the Graphics Processing Unit (GPU),
 /// which handles rendering and graphical operations.
 pub mod gpu;
 
+/// The `instruction` module decodes opcodes into instructions, which the
+/// bus caches by address.
+pub mod instruction;
+
+/// The `machine` module contains the interface between the emulated
+/// machines and the frontend.
+pub mod machine;
+
+/// The `profile` module describes the memory layouts of the machines
+/// that ran CHIP-8 programs.
+pub mod profile;
+
+/// The `quirk_watch` module reports the instructions of a running program
+/// whose outcome depends on a quirk.
+pub mod quirk_watch;
+
+/// The `quirks` module contains the behaviours that differ between
+/// CHIP-8 interpreters.
+pub mod quirks;
+
+/// The `shadow` module runs two machines side by side, to find where
+/// different quirks make a program behave differently.
+pub mod shadow;
+
+/// The `timing` module contains the instruction timings of the COSMAC VIP.
+pub mod timing;
+
+/// The `vip` module emulates the COSMAC VIP itself, to run the original
+/// CHIP-8 interpreter.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod vip;
+
 // Re-exporting common components for easier access.
 pub use bus::Bus;
 pub use cpu::Cpu;
 pub use gpu::Gpu;
+pub use machine::Machine;
 
 /// Extracts the least significant nibble (lowest 4 bits) from the given opcode.
 #[macro_export]
@@ -0,0 +1,186 @@
+use super::bus::Bus;
+use super::instruction::Instruction;
+
+/// Longest block compiled; longer runs are split over several blocks
+const MAX_BLOCK: usize = 64;
+
+/// How the CPU runs instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Fetches and runs one instruction at a time
+    #[default]
+    Interpreter,
+    /// Runs straight-line blocks of instructions compiled ahead, and skips
+    /// the rest of a frame spent in a loop that can't be left, for batch
+    /// runs; only with `Timing::Instructions` and without a quirk watch,
+    /// otherwise the interpreter is used
+    Blocks,
+}
+
+/// A run of instructions that neither jump, skip, draw nor write memory, so
+/// it always runs to its end and can't change itself, and the instruction
+/// after it that may do any of these
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// The bus's `code_version` the block was compiled at
+    version: u32,
+    pub straight: Box<[Instruction]>,
+    /// Missing when the run stops at the end of memory or at `MAX_BLOCK`
+    pub end: Option<Instruction>,
+    /// Whether the block only reads memory, the keys and the delay timer,
+    /// and changes nothing but PC, V and I
+    pub pure: bool,
+}
+
+/// Blocks by their start address
+#[derive(Debug, Default)]
+pub struct BlockCache {
+    blocks: Vec<Option<Block>>,
+}
+
+impl Clone for BlockCache {
+    /// An empty cache: blocks are compiled again as they run, which costs
+    /// less than copying one slot per byte of memory on every clone
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl BlockCache {
+    /// Takes out the block at `address`, compiled if it isn't yet or code was
+    /// written over; `put` gives it back once it has run
+    pub fn take(&mut self, bus: &mut Bus, address: u16) -> Block {
+        let size = bus.memory().len();
+        if self.blocks.len() != size {
+            self.blocks = vec![None; size];
+        }
+        let index = address as usize % size;
+        let version = bus.code_version();
+        match self.blocks[index].take() {
+            Some(block) if block.version == version => block,
+            _ => compile(bus, index, version),
+        }
+    }
+
+    pub fn put(&mut self, address: u16, block: Block) {
+        let index = address as usize % self.blocks.len();
+        self.blocks[index] = Some(block);
+    }
+}
+
+fn compile(bus: &mut Bus, index: usize, version: u32) -> Block {
+    let mut straight = Vec::new();
+    let mut end = None;
+    let mut address = index;
+    // a block doesn't wrap around the end of memory
+    while straight.len() < MAX_BLOCK && address + 1 < bus.memory().len() {
+        let instruction = bus.instruction(address as u16);
+        if !is_straight(instruction) {
+            end = Some(instruction);
+            break;
+        }
+        straight.push(instruction);
+        address += 2;
+    }
+    let pure = !straight.iter().any(|&instruction| has_effects(instruction))
+        && end.map_or(true, is_branch);
+    Block {
+        version,
+        straight: straight.into(),
+        end,
+        pure,
+    }
+}
+
+/// Finds loops of pure blocks, which the machine can't leave before the
+/// frame ends, since the keys and the delay timer only change between
+/// frames. Looks at the states between blocks, with Brent's cycle detection.
+#[derive(Debug, Default)]
+pub struct LoopFinder {
+    /// PC, V and I saved, and the instructions run by then
+    saved: Option<(u16, [u8; 16], u16, u32)>,
+    /// Blocks since the state was saved, and how many until it is saved again
+    steps: u32,
+    power: u32,
+}
+
+impl LoopFinder {
+    /// After a block has run, with `count` instructions run so far; returns
+    /// the length of the loop the machine is in, if it is in one
+    pub fn after(&mut self, pure: bool, pc: u16, v: &[u8; 16], i: u16, count: u32) -> Option<u32> {
+        if !pure {
+            *self = Self::default();
+            return None;
+        }
+        if let Some((saved_pc, saved_v, saved_i, saved_count)) = self.saved {
+            if saved_pc == pc && saved_v == *v && saved_i == i {
+                return Some(count - saved_count);
+            }
+        }
+        self.steps += 1;
+        if self.steps >= self.power {
+            self.saved = Some((pc, *v, i, count));
+            self.steps = 0;
+            self.power = (self.power * 2).max(1);
+        }
+        None
+    }
+}
+
+/// Straight instructions that change more than V and I, or the display
+/// in a way that running them again doesn't undo
+fn has_effects(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Random(..) | Instruction::SetDelay(..) | Instruction::SetSound(..)
+    )
+}
+
+/// Instructions that only choose where to go on
+fn is_branch(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        Jump(..)
+            | JumpWithOffset(..)
+            | SkipIfEqual(..)
+            | SkipIfNotEqual(..)
+            | SkipIfRegistersEqual(..)
+            | SkipIfRegistersNotEqual(..)
+            | SkipIfKey(..)
+            | SkipIfNotKey(..)
+    )
+}
+
+/// Instructions that go on to the next one, and only change registers or the display
+fn is_straight(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        ClearScreen
+            | Lores
+            | Hires
+            | Set(..)
+            | Add(..)
+            | Copy(..)
+            | Or(..)
+            | And(..)
+            | Xor(..)
+            | AddRegisters(..)
+            | Subtract(..)
+            | ShiftRight(..)
+            | SubtractReversed(..)
+            | ShiftLeft(..)
+            | SetIndex(..)
+            | Random(..)
+            | GetDelay(..)
+            | SetDelay(..)
+            | SetSound(..)
+            | AddToIndex(..)
+            | Font(..)
+            | BigFont(..)
+            | Load(..)
+    )
+}
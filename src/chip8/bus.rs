@@ -1,30 +1,11 @@
-use super::gpu::Gpu;
+use super::font::FontSet;
+use super::gpu::{Gpu, SpriteHits};
+use super::instruction::Instruction;
+use super::profile::MachineProfile;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, Read};
 
-const MEMORY_SIZE: usize = 0x1000; // 4Kb
-
-#[rustfmt::skip]
-const FONT_BYTES: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
 #[allow(dead_code)]
 fn read_binary_file(path: &str) -> io::Result<Vec<u8>> {
     let mut file = File::open(path)?;
@@ -34,42 +15,173 @@ fn read_binary_file(path: &str) -> io::Result<Vec<u8>> {
 }
 
 pub struct Bus {
-    pub memory: [u8; MEMORY_SIZE],
+    /// Writes go through `save_byte` or `load_rom`, which keep `decoded` up to date
+    memory: Vec<u8>,
+    /// The instructions decoded so far, by address; empty until the first
+    /// one is
+    decoded: Vec<Option<Instruction>>,
+    /// Changes whenever a decoded instruction is written over
+    code_version: u32,
     pub gpu: Gpu,
+    pub font: FontSet,
+    pub profile: MachineProfile,
+}
+
+impl Clone for Bus {
+    fn clone(&self) -> Self {
+        Bus {
+            memory: self.memory.clone(),
+            decoded: Vec::new(),
+            code_version: self.code_version,
+            gpu: self.gpu.clone(),
+            font: self.font,
+            profile: self.profile,
+        }
+    }
+
+    /// Copies `source` into the memory this bus already has. Like a clone,
+    /// it leaves the instructions to be decoded again, which costs less than
+    /// copying one slot per byte of memory.
+    fn clone_from(&mut self, source: &Self) {
+        self.memory.clone_from(&source.memory);
+        self.decoded.clear();
+        self.code_version = source.code_version;
+        self.gpu.clone_from(&source.gpu);
+        self.font = source.font;
+        self.profile = source.profile;
+    }
 }
 
 impl Bus {
-    pub fn new() -> Self {
+    pub fn new(profile: MachineProfile) -> Self {
         let mut new_bus = Bus {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; profile.memory_size],
+            decoded: Vec::new(),
+            code_version: 0,
             gpu: Gpu::new(),
+            font: profile.font,
+            profile,
         };
-        new_bus.load_font();
+        new_bus.gpu.set_size(profile.display.0, profile.display.1);
+        new_bus.load_font(profile.font);
 
         new_bus
     }
 
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
-        self.memory[(address as usize) % MEMORY_SIZE]
+        self.memory[(address as usize) % self.memory.len()]
     }
 
     pub fn save_byte(&mut self, address: u16, data: u8) {
-        self.memory[(address as usize) % MEMORY_SIZE] = data;
+        let size = self.memory.len();
+        let index = (address as usize) % size;
+        self.memory[index] = data;
+        // the byte is in the instructions that start here and just before
+        let previous = (index + size - 1) % size;
+        let decoded = |index: usize| matches!(self.decoded.get(index), Some(Some(_)));
+        if decoded(index) || decoded(previous) {
+            self.decoded[index] = None;
+            self.decoded[previous] = None;
+            self.code_version = self.code_version.wrapping_add(1);
+        }
+    }
+
+    pub fn code_version(&self) -> u32 {
+        self.code_version
+    }
+
+    /// The instruction at `address`, decoded once until it is written over;
+    /// invalid opcodes are reported as they are decoded
+    pub fn instruction(&mut self, address: u16) -> Instruction {
+        let size = self.memory.len();
+        let mut index = address as usize;
+        if index >= size {
+            index %= size;
+        }
+        if self.decoded.is_empty() {
+            self.decoded.resize(size, None);
+        }
+        match self.decoded[index] {
+            Some(instruction) => instruction,
+            None => {
+                let opcode =
+                    u16::from_be_bytes([self.memory[index], self.memory[(index + 1) % size]]);
+                let instruction = Instruction::decode(opcode);
+                if let Instruction::Invalid(opcode) = instruction {
+                    log::warn!("Invalid opcode {:04X} at {:04X}", opcode, index);
+                }
+                self.decoded[index] = Some(instruction);
+                instruction
+            }
+        }
     }
 
-    pub fn load_font(&mut self) {
-        // it’s become popular to put it at 050–09F
-        // instruction Fx29 relies on this base address
-        self.memory[0x50..0xa0].copy_from_slice(&FONT_BYTES);
+    pub fn load_font(&mut self, font: FontSet) {
+        // instructions Fx29 and Fx30 rely on these base addresses
+        let fonts = [
+            (self.profile.font_address, font.small.glyphs),
+            (self.profile.big_font_address, font.big.glyphs),
+        ];
+        for (address, glyphs) in fonts {
+            for (offset, &data) in glyphs.iter().enumerate() {
+                self.save_byte(address.wrapping_add(offset as u16), data);
+            }
+        }
+        self.font = font;
     }
 
     pub fn load_rom(&mut self, source: &[u8]) {
-        let to_idx = 0x200 + source.len();
-        self.memory[0x200..to_idx].copy_from_slice(source);
+        let fits = self
+            .memory
+            .len()
+            .saturating_sub(self.profile.program_start as usize);
+        if source.len() > fits {
+            log::warn!(
+                "The program is {} bytes, but only {} fit in memory",
+                source.len(),
+                fits
+            );
+        }
+        self.load(self.profile.program_start, source);
+    }
+
+    /// Writes `data` to memory from `address` on; anything that doesn't fit
+    /// is cut off
+    pub fn load(&mut self, address: u16, data: &[u8]) {
+        let from_idx = (address as usize).min(self.memory.len());
+        let to_idx = (from_idx + data.len()).min(self.memory.len());
+        self.memory[from_idx..to_idx].copy_from_slice(&data[..to_idx - from_idx]);
+        self.decoded.clear();
+        self.code_version = self.code_version.wrapping_add(1);
     }
 
-    pub fn display(&mut self, x_coord: u8, y_coord: u8, address: u16) -> bool {
-        let sprite_data = self.read_byte(address);
-        self.gpu.draw_sprite_line(x_coord, y_coord, sprite_data)
+    /// Draws the sprite at `address`: `n` rows of 8 pixels, or 16x16 pixels
+    /// (SUPER-CHIP) when `n` is 0
+    pub fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        address: u16,
+        n: u8,
+        wrap: bool,
+    ) -> SpriteHits {
+        let rows: Vec<u16> = if n == 0 {
+            (0..16)
+                .map(|row| address.wrapping_add(row * 2))
+                .map(|addr| {
+                    u16::from_be_bytes([self.read_byte(addr), self.read_byte(addr.wrapping_add(1))])
+                })
+                .collect()
+        } else {
+            (0..n as u16)
+                .map(|row| self.read_byte(address.wrapping_add(row)) as u16)
+                .collect()
+        };
+        let bits = if n == 0 { 16 } else { 8 };
+        self.gpu.draw_sprite(x, y, &rows, bits, wrap)
     }
 }
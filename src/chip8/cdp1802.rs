@@ -0,0 +1,328 @@
+/// Memory and I/O as seen by the RCA CDP1802
+pub trait Cdp1802Bus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, data: u8);
+
+    /// OUT 1-7 puts a byte from memory on the data bus for port `port`
+    fn output(&mut self, _port: u8, _data: u8) {}
+
+    /// INP 1-7 reads a byte from port `port` into memory and D
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    /// The state of the external flag inputs EF1-EF4, `line` from 1 to 4
+    fn flag(&self, _line: u8) -> bool {
+        false
+    }
+}
+
+impl Cdp1802Bus for super::Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.save_byte(address, data);
+    }
+}
+
+/// The RCA CDP1802 (COSMAC) microprocessor of the COSMAC VIP
+#[derive(Debug, Clone, Default)]
+pub struct Cdp1802 {
+    /// Sixteen 16-bit scratchpad registers, R0 to RF
+    pub r: [u16; 16],
+    /// The accumulator
+    pub d: u8,
+    /// The carry/borrow flag
+    pub df: bool,
+    /// Selects the register that is the program counter
+    pub p: u8,
+    /// Selects the register that is the data pointer
+    pub x: u8,
+    /// Holds X and P while an interrupt is served
+    pub t: u8,
+    /// Interrupts are enabled
+    pub ie: bool,
+    /// The Q output; on the VIP it drives the tone generator
+    pub q: bool,
+    /// Set by IDL, until the next interrupt or DMA request
+    pub idle: bool,
+}
+
+impl Cdp1802 {
+    /// The state after a reset: R0 is the program counter, interrupts enabled
+    pub fn new() -> Self {
+        Self {
+            ie: true,
+            ..Self::default()
+        }
+    }
+
+    fn fetch<B: Cdp1802Bus>(&mut self, bus: &mut B) -> u8 {
+        let pc = self.p as usize;
+        let data = bus.read(self.r[pc]);
+        self.r[pc] = self.r[pc].wrapping_add(1);
+        data
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn inc_rx(&mut self) {
+        let x = self.x as usize;
+        self.r[x] = self.r[x].wrapping_add(1);
+    }
+
+    /// Serves an interrupt request, if interrupts are enabled; like the DMA
+    /// below, only the emulated VIP, which is native only, asks for it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn interrupt(&mut self) {
+        if self.ie {
+            self.t = (self.x << 4) | self.p;
+            self.x = 2;
+            self.p = 1;
+            self.ie = false;
+            self.idle = false;
+        }
+    }
+
+    /// A DMA output cycle: the byte at R0 goes to the device, and R0 moves on
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn dma_out<B: Cdp1802Bus>(&mut self, bus: &mut B) -> u8 {
+        let data = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        data
+    }
+
+    /// Executes one instruction and returns the machine cycles it took
+    pub fn step<B: Cdp1802Bus>(&mut self, bus: &mut B) -> u32 {
+        if self.idle {
+            return 1;
+        }
+
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0xf) as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => {
+                // IDL - wait for an interrupt or DMA request
+                self.idle = true;
+            }
+            0x0 => {
+                // LDN - load via N
+                self.d = bus.read(self.r[n]);
+            }
+            0x1 => {
+                // INC - increment register N
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x2 => {
+                // DEC - decrement register N
+                self.r[n] = self.r[n].wrapping_sub(1);
+            }
+            0x3 => {
+                // short branches: 30-37 branch if the condition holds, 38-3F if not,
+                // within the page of the target byte
+                let page = self.r[self.p as usize] & 0xff00;
+                let target = self.fetch(bus);
+                if self.condition(bus, n & 0x7) != (n >= 8) {
+                    self.r[self.p as usize] = page | target as u16;
+                }
+            }
+            0x4 => {
+                // LDA - load advance
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => {
+                // STR - store via N
+                bus.write(self.r[n], self.d);
+            }
+            0x6 => match n {
+                0x0 => self.inc_rx(),
+                0x8 => (),
+                0x1..=0x7 => {
+                    // OUT - output from memory, increment RX
+                    let data = bus.read(self.rx());
+                    bus.output(n as u8, data);
+                    self.inc_rx();
+                }
+                _ => {
+                    // INP - input to memory and D
+                    self.d = bus.input(n as u8 - 8);
+                    bus.write(self.rx(), self.d);
+                }
+            },
+            0x7 => self.op_7n(bus, n),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xa => self.r[n] = (self.r[n] & 0xff00) | self.d as u16,
+            0xb => self.r[n] = (self.r[n] & 0x00ff) | ((self.d as u16) << 8),
+            0xc => {
+                self.long_branch(bus, n);
+                return 3;
+            }
+            0xd => self.p = n as u8,
+            0xe => self.x = n as u8,
+            _ => self.op_fn(bus, n),
+        }
+        2
+    }
+
+    /// Conditions of the short and long branches, by the low 3 bits of the opcode
+    fn condition<B: Cdp1802Bus>(&self, bus: &B, n: usize) -> bool {
+        match n {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            _ => bus.flag(n as u8 - 3),
+        }
+    }
+
+    fn long_branch<B: Cdp1802Bus>(&mut self, bus: &mut B, n: usize) {
+        let pc = self.p as usize;
+        match n {
+            // LBR, LBQ, LBZ, LBDF and LBNQ, LBNZ, LBNF
+            0x0..=0x3 | 0x9..=0xb => {
+                let condition = match n & 0x3 {
+                    0 => true,
+                    1 => self.q,
+                    2 => self.d == 0,
+                    _ => self.df,
+                };
+                if condition != (n >= 8) {
+                    let high = bus.read(self.r[pc]);
+                    let low = bus.read(self.r[pc].wrapping_add(1));
+                    self.r[pc] = u16::from_be_bytes([high, low]);
+                } else {
+                    self.r[pc] = self.r[pc].wrapping_add(2);
+                }
+            }
+            // NOP
+            0x4 => (),
+            // LSNQ, LSNZ, LSNF, LSKP and LSIE, LSQ, LSZ, LSDF
+            _ => {
+                let skip = match n {
+                    0x5 => !self.q,
+                    0x6 => self.d != 0,
+                    0x7 => !self.df,
+                    0x8 => true,
+                    0xc => self.ie,
+                    0xd => self.q,
+                    0xe => self.d == 0,
+                    _ => self.df,
+                };
+                if skip {
+                    self.r[pc] = self.r[pc].wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    fn op_7n<B: Cdp1802Bus>(&mut self, bus: &mut B, n: usize) {
+        match n {
+            0x0 | 0x1 => {
+                // RET and DIS - restore X and P, and enable or disable interrupts
+                let xp = bus.read(self.rx());
+                self.inc_rx();
+                self.x = xp >> 4;
+                self.p = xp & 0xf;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                // LDXA - load via X and advance
+                self.d = bus.read(self.rx());
+                self.inc_rx();
+            }
+            0x3 => {
+                // STXD - store via X and decrement
+                bus.write(self.rx(), self.d);
+                let x = self.x as usize;
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x6 => {
+                // SHRC - shift right with carry
+                let carry = self.d & 1 != 0;
+                self.d = (self.d >> 1) | ((self.df as u8) << 7);
+                self.df = carry;
+            }
+            0x8 => {
+                // SAV - save T
+                bus.write(self.rx(), self.t);
+            }
+            0x9 => {
+                // MARK - push X and P, X becomes P
+                self.t = (self.x << 4) | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xa => self.q = false,
+            0xb => self.q = true,
+            0xe => {
+                // SHLC - shift left with carry
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                // ADC, SDB, SMB via X and ADCI, SDBI, SMBI immediate
+                let operand = if n >= 0xc {
+                    self.fetch(bus)
+                } else {
+                    bus.read(self.rx())
+                };
+                self.arithmetic(n & 0x7, operand, self.df);
+            }
+        }
+    }
+
+    fn op_fn<B: Cdp1802Bus>(&mut self, bus: &mut B, n: usize) {
+        match n {
+            0x0 => self.d = bus.read(self.rx()),
+            0x6 => {
+                // SHR - shift right
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0xe => {
+                // SHL - shift left
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => {
+                // F1-F7 operate on M(RX), F8-FF on the immediate byte
+                let operand = if n >= 0x8 {
+                    self.fetch(bus)
+                } else {
+                    bus.read(self.rx())
+                };
+                match n & 0x7 {
+                    0x0 => self.d = operand,
+                    0x1 => self.d |= operand,
+                    0x2 => self.d &= operand,
+                    0x3 => self.d ^= operand,
+                    // subtracting is adding the complement plus one
+                    op => self.arithmetic(op, operand, op != 0x4),
+                }
+            }
+        }
+    }
+
+    /// ADD/ADC (4), SD/SDB (5) and SM/SMB (7) with a carry in; DF is the
+    /// carry out, or the inverted borrow of the subtractions
+    fn arithmetic(&mut self, op: usize, operand: u8, carry: bool) {
+        let (a, b) = match op {
+            0x4 => (self.d, operand),
+            0x5 => (operand, !self.d),
+            _ => (self.d, !operand),
+        };
+        let result = a as u16 + b as u16 + carry as u16;
+        self.d = result as u8;
+        self.df = result > 0xff;
+    }
+}
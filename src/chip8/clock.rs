@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Rate of the delay and sound timers, and of the display's vertical blank
+pub const TIMER_RATE: u32 = 60;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The machine's clock, which turns time passed into timer ticks and the
+/// instructions to run before each. Instructions and timers run at rates of
+/// their own, so a tick needn't get a whole number of instructions, and the
+/// frontend can feed it time in steps of any size.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    /// Instructions per second
+    pub instruction_rate: u32,
+    /// Timer ticks per second
+    pub timer_rate: u32,
+    /// Nanoseconds since the last tick, times `timer_rate`
+    phase: u64,
+    /// Instructions owed to the next tick, times `timer_rate`
+    owed: u64,
+}
+
+impl Clock {
+    pub fn new(instruction_rate: u32, timer_rate: u32) -> Self {
+        Clock {
+            instruction_rate,
+            timer_rate,
+            phase: 0,
+            owed: 0,
+        }
+    }
+
+    /// A clock that runs `ticks_per_frame` instructions per 60Hz timer tick
+    pub fn with_tickrate(ticks_per_frame: u16) -> Self {
+        Self::new(ticks_per_frame as u32 * TIMER_RATE, TIMER_RATE)
+    }
+
+    /// Moves the clock on by `time`; returns the timer ticks that fall in it
+    pub fn advance(&mut self, time: Duration) -> u32 {
+        let nanos = time.as_nanos().min(u64::MAX as u128 / 2) as u64;
+        self.phase = self
+            .phase
+            .saturating_add(nanos.saturating_mul(self.timer_rate as u64));
+        let ticks = self.phase / NANOS_PER_SECOND;
+        self.phase %= NANOS_PER_SECOND;
+        ticks.min(u32::MAX as u64) as u32
+    }
+
+    /// The instructions to run before the next timer tick. Fractions of an
+    /// instruction are carried over, so that ticks add up to the instruction rate.
+    pub fn tick_instructions(&mut self) -> u16 {
+        self.owed += self.instruction_rate as u64;
+        let instructions = self.owed / self.timer_rate.max(1) as u64;
+        self.owed %= self.timer_rate.max(1) as u64;
+        instructions.min(u16::MAX as u64) as u16
+    }
+
+    /// Time left until the next timer tick
+    pub fn until_tick(&self) -> Duration {
+        let left = NANOS_PER_SECOND - self.phase;
+        Duration::from_nanos(left.div_ceil(self.timer_rate.max(1) as u64))
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::with_tickrate(10)
+    }
+}
@@ -1,39 +1,259 @@
 use crate::chip8;
+use crate::chip8::blocks::{BlockCache, Engine, LoopFinder};
+use crate::chip8::cdp1802::Cdp1802;
+use crate::chip8::gpu::{self, Gpu};
+use crate::chip8::instruction::Instruction;
+use crate::chip8::machine::Machine;
+use crate::chip8::profile::{vip, MachineProfile};
+use crate::chip8::quirk_watch::QuirkWatch;
+use crate::chip8::quirks::Quirks;
+use crate::chip8::timing::{self, Timing};
 use crate::{N, NN, NNN, X, Y};
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::fmt;
 
+/// What 2NNN does when the stack is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackOverflow {
+    /// Drops the oldest return address to make room
+    #[default]
+    Wrap,
+    /// Logs an error and stops the program at the call, or at a return
+    /// without a call
+    Error,
+    /// Keeps the return addresses in memory, growing down from the profile's
+    /// stack address, like the COSMAC VIP interpreter from 0ECF, so that deep
+    /// calls write over the memory below and programs can change where they
+    /// return to; the stack depth doesn't apply
+    Memory,
+}
+
+/// Upper limit on the machine cycles of the machine code routines run in one
+/// frame, so a routine that never returns can't hang the emulator
+const MAX_MACHINE_CODE_CYCLES: u32 = 1_000_000;
+
+/// Frames the key click sounds for
+const CLICK_FRAMES: u8 = 4;
+
+/// FX0A waiting for a key, which goes to VX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyWait {
+    x: usize,
+    /// The key pressed, while it is waited to be released
+    pressed: Option<usize>,
+}
+
 pub struct Cpu {
     pub bus: chip8::Bus,
     pub keys_down: [bool; 16],
     pub pc: u16,
     i: u16,
     stack: Vec<u16>,
+    /// Return addresses the stack holds, from the profile; a stack in memory
+    /// has no limit
+    pub stack_depth: usize,
+    pub stack_overflow: StackOverflow,
+    /// The next free byte of the stack in memory, for `StackOverflow::Memory`
+    stack_pointer: u16,
+    /// Why the program stopped, if it did
+    error: Option<String>,
     delay_timer: u8,
     pub sound_timer: u8,
     v: [u8; 16],
-    key_pressed: Option<usize>,
+    /// Set while FX0A waits; no instructions run until it is over
+    key_wait: Option<KeyWait>,
+    /// The key FX0A took at its press, with the `key_wait_press` quirk; it
+    /// reads as up until it is released, so that the press is seen once
+    held_key: Option<usize>,
+    /// Sounds the buzzer briefly when FX0A gets a key
+    pub key_click: bool,
+    click_timer: u8,
+    pub timing: Timing,
+    pub engine: Engine,
+    pub quirks: Quirks,
+    /// Reports quirk-sensitive instructions while set
+    pub quirk_watch: Option<QuirkWatch>,
+    /// Set by DXYN with the vblank quirk, or always with `Timing::VipCycles`;
+    /// cleared at the next frame boundary
+    waiting_for_vblank: bool,
+    /// Machine cycles left in the current frame, in `Timing::VipCycles` mode
+    cycles: i32,
+    /// Runs the machine code routines called by 0NNN
+    cdp1802: Cdp1802,
+    /// Machine cycles taken by the last machine code routine
+    machine_code_cycles: u32,
+    /// Machine cycles that machine code routines may still take this frame
+    machine_code_budget: u32,
+    /// Random numbers for CXNN; seeded, so that runs can be repeated
+    rng: StdRng,
+    /// Compiled blocks, for `Engine::Blocks`
+    blocks: BlockCache,
+}
+
+impl Clone for Cpu {
+    fn clone(&self) -> Self {
+        Cpu {
+            bus: self.bus.clone(),
+            keys_down: self.keys_down,
+            pc: self.pc,
+            i: self.i,
+            stack: self.stack.clone(),
+            stack_depth: self.stack_depth,
+            stack_overflow: self.stack_overflow,
+            stack_pointer: self.stack_pointer,
+            error: self.error.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            v: self.v,
+            key_wait: self.key_wait,
+            held_key: self.held_key,
+            key_click: self.key_click,
+            click_timer: self.click_timer,
+            timing: self.timing,
+            engine: self.engine,
+            quirks: self.quirks,
+            quirk_watch: self.quirk_watch.clone(),
+            waiting_for_vblank: self.waiting_for_vblank,
+            cycles: self.cycles,
+            cdp1802: self.cdp1802.clone(),
+            machine_code_cycles: self.machine_code_cycles,
+            machine_code_budget: self.machine_code_budget,
+            rng: self.rng.clone(),
+            blocks: self.blocks.clone(),
+        }
+    }
+
+    /// Copies `source` field by field, so that a snapshot taken every frame
+    /// reuses the memory it already has
+    fn clone_from(&mut self, source: &Self) {
+        let Cpu {
+            bus,
+            keys_down,
+            pc,
+            i,
+            stack,
+            stack_depth,
+            stack_overflow,
+            stack_pointer,
+            error,
+            delay_timer,
+            sound_timer,
+            v,
+            key_wait,
+            held_key,
+            key_click,
+            click_timer,
+            timing,
+            engine,
+            quirks,
+            quirk_watch,
+            waiting_for_vblank,
+            cycles,
+            cdp1802,
+            machine_code_cycles,
+            machine_code_budget,
+            rng,
+            blocks,
+        } = self;
+        bus.clone_from(&source.bus);
+        keys_down.clone_from(&source.keys_down);
+        pc.clone_from(&source.pc);
+        i.clone_from(&source.i);
+        stack.clone_from(&source.stack);
+        stack_depth.clone_from(&source.stack_depth);
+        stack_overflow.clone_from(&source.stack_overflow);
+        stack_pointer.clone_from(&source.stack_pointer);
+        error.clone_from(&source.error);
+        delay_timer.clone_from(&source.delay_timer);
+        sound_timer.clone_from(&source.sound_timer);
+        v.clone_from(&source.v);
+        key_wait.clone_from(&source.key_wait);
+        held_key.clone_from(&source.held_key);
+        key_click.clone_from(&source.key_click);
+        click_timer.clone_from(&source.click_timer);
+        timing.clone_from(&source.timing);
+        engine.clone_from(&source.engine);
+        quirks.clone_from(&source.quirks);
+        quirk_watch.clone_from(&source.quirk_watch);
+        waiting_for_vblank.clone_from(&source.waiting_for_vblank);
+        cycles.clone_from(&source.cycles);
+        cdp1802.clone_from(&source.cdp1802);
+        machine_code_cycles.clone_from(&source.machine_code_cycles);
+        machine_code_budget.clone_from(&source.machine_code_budget);
+        rng.clone_from(&source.rng);
+        blocks.clone_from(&source.blocks);
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
     pub fn new() -> Self {
+        Self::with_profile(MachineProfile::default())
+    }
+
+    pub fn with_profile(profile: MachineProfile) -> Self {
+        let start = profile.program_start;
         let mut cpu = Cpu {
-            bus: chip8::Bus::new(),
+            bus: chip8::Bus::new(profile),
             keys_down: [false; 16],
-            pc: 0x200,
+            pc: start,
             i: 0,
             stack: Vec::new(),
+            stack_depth: profile.stack_depth,
+            stack_overflow: StackOverflow::Wrap,
+            stack_pointer: profile.stack_address,
+            error: None,
             delay_timer: 0,
             sound_timer: 0,
             v: [0_u8; 16],
-            key_pressed: None,
+            key_wait: None,
+            held_key: None,
+            key_click: false,
+            click_timer: 0,
+            timing: Timing::Instructions,
+            engine: Engine::Interpreter,
+            quirks: Quirks::default(),
+            quirk_watch: None,
+            waiting_for_vblank: false,
+            cycles: 0,
+            cdp1802: Cdp1802::new(),
+            machine_code_cycles: 0,
+            machine_code_budget: MAX_MACHINE_CODE_CYCLES,
+            rng: StdRng::from_entropy(),
+            blocks: BlockCache::default(),
         };
-        cpu.bus.save_byte(0x200, 0x12);
-        // cpu.bus.save_byte(0x201, 0x1200);
+        // jump to self until a program is loaded
+        cpu.bus.save_byte(start, 0x10 | (start >> 8) as u8);
+        cpu.bus.save_byte(start + 1, start as u8);
         cpu
     }
 }
 
+impl Machine for Cpu {
+    fn run_frame(&mut self, ticks: u16) {
+        self.ticks(ticks);
+    }
+
+    fn set_keys(&mut self, keys_down: [bool; 16]) {
+        self.keys_down = keys_down;
+    }
+
+    fn is_beeping(&self) -> bool {
+        self.sound_timer > 0 || self.click_timer > 0
+    }
+
+    fn gpu(&mut self) -> &mut Gpu {
+        &mut self.bus.gpu
+    }
+}
+
 impl fmt::Debug for Cpu {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Start the struct formatting
@@ -41,13 +261,13 @@ impl fmt::Debug for Cpu {
 
         // Format `pc` and `i` with 4 hexadecimal characters without 0x prefix
         debug_struct.field("pc", &format_args!("{:04X}", self.pc));
-        let opcode: u16 =
-            ((self.bus.read_byte(self.pc) as u16) << 8) | (self.bus.read_byte(self.pc + 1) as u16);
-        debug_struct.field("op", &format_args!("{:04X}", opcode));
+        debug_struct.field("op", &format_args!("{:04X}", self.get_op()));
 
         debug_struct.field("i", &format_args!("{:04X}", self.i));
-        let idata: u16 =
-            ((self.bus.read_byte(self.i) as u16) << 8) | (self.bus.read_byte(self.i + 1) as u16);
+        let idata = u16::from_be_bytes([
+            self.bus.read_byte(self.i),
+            self.bus.read_byte(self.i.wrapping_add(1)),
+        ]);
         debug_struct.field("data", &format_args!("{:04X}", idata));
 
         // Format `v` array with 2 hexadecimal characters per element without 0x prefix
@@ -82,7 +302,9 @@ pub fn fmt_opcode(opcode: u16) -> String {
         0x0000 => match opcode & 0xfff {
             0x0e0 => "00E0 Clear".to_string(),
             0x0ee => "00EE Return".to_string(),
-            _ => "0___ Not implemented".to_string(),
+            0x0fe => "00FE Low resolution".to_string(),
+            0x0ff => "00FF High resolution".to_string(),
+            _ => format!("0NNN Call machine code at {}", NNN!(opcode)),
         },
         0x1000 => format!("1NNN Jump to {}", NNN!(opcode)),
         0x2000 => format!("2NNN Call sub at {}", NNN!(opcode)),
@@ -139,8 +361,12 @@ pub fn fmt_opcode(opcode: u16) -> String {
         },
         0x9000 => format!("9XY0 Skip if V{} not equals V{}", X!(opcode), Y!(opcode)),
         0xa000 => format!("ANNN Set I to {}", NNN!(opcode)),
-        0xb000 => format!("BNNN Jump to {} + V0", NNN!(opcode)),
-        0xc000 => "CNNN Not implemented yet".to_string(),
+        0xb000 => format!(
+            "BNNN Jump to {} + V0, or + V{} with the jump quirk",
+            NNN!(opcode),
+            X!(opcode)
+        ),
+        0xc000 => format!("CXNN Set V{} to random AND {}", X!(opcode), NN!(opcode)),
         0xd000 => format!(
             "DXYN Display {} rows at V{},V{} with carry",
             N!(opcode),
@@ -156,7 +382,7 @@ pub fn fmt_opcode(opcode: u16) -> String {
             0x07 => {
                 format!("FX07 Set V{} to delay timer", X!(opcode))
             }
-            0x0a => format!("FX0A Set V{} to first inp, or decr PC", X!(opcode)),
+            0x0a => format!("FX0A Wait for key, store in V{}", X!(opcode)),
             0x15 => {
                 format!("FX15 Set delay timer to V{}", X!(opcode))
             }
@@ -165,6 +391,7 @@ pub fn fmt_opcode(opcode: u16) -> String {
             }
             0x1e => format!("FX1E Add V{} to I", X!(opcode)),
             0x29 => format!("FX29 Set I to addr of font char in V{}", X!(opcode)),
+            0x30 => format!("FX30 Set I to addr of big font char in V{}", X!(opcode)),
             0x33 => format!("FX33 Store BCD of V{} in M[I], incr I", X!(opcode)),
             0x55 => {
                 format!("FX55 Store V0..V{} in M[I], incr I (VIP impl)", X!(opcode))
@@ -177,15 +404,234 @@ pub fn fmt_opcode(opcode: u16) -> String {
 }
 
 impl Cpu {
+    /// Makes CXNN return the same numbers on every run with the same seed
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    /// For tests, which set up the registers directly
+    #[cfg(feature = "testing")]
+    pub fn v_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.v
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// For tests
+    #[cfg(feature = "testing")]
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// For tests
+    #[cfg(feature = "testing")]
+    pub fn set_delay_timer(&mut self, delay_timer: u8) {
+        self.delay_timer = delay_timer;
+    }
+
+    /// The return addresses on the stack, the latest last
+    pub fn stack(&self) -> Vec<u16> {
+        match self.stack_overflow {
+            StackOverflow::Memory => {
+                let top = self.bus.profile.stack_address;
+                let depth = top.saturating_sub(self.stack_pointer) / 2;
+                (0..depth)
+                    .map(|n| {
+                        let low = top.wrapping_sub(2 * n);
+                        u16::from_be_bytes([
+                            self.bus.read_byte(low.wrapping_sub(1)),
+                            self.bus.read_byte(low),
+                        ])
+                    })
+                    .collect()
+            }
+            _ => self.stack.clone(),
+        }
+    }
+
+    /// Why the program stopped, if it did
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Describes the first difference in registers, memory or display
+    /// between two machines, or None if they are in the same state
+    pub fn difference(&self, other: &Cpu) -> Option<String> {
+        if self.pc != other.pc {
+            return Some(format!("PC is {:04X} vs {:04X}", self.pc, other.pc));
+        }
+        if self.i != other.i {
+            return Some(format!("I is {:04X} vs {:04X}", self.i, other.i));
+        }
+        if let Some(x) = (0..16).find(|&x| self.v[x] != other.v[x]) {
+            return Some(format!(
+                "V{:X} is {:02X} vs {:02X}",
+                x, self.v[x], other.v[x]
+            ));
+        }
+        if self.stack != other.stack {
+            return Some(format!(
+                "the stack is {:04X?} vs {:04X?}",
+                self.stack, other.stack
+            ));
+        }
+        if (self.delay_timer, self.sound_timer) != (other.delay_timer, other.sound_timer) {
+            return Some(format!(
+                "the timers are {}/{} vs {}/{}",
+                self.delay_timer, self.sound_timer, other.delay_timer, other.sound_timer
+            ));
+        }
+        if self.stack_pointer != other.stack_pointer {
+            return Some(format!(
+                "the stack pointer is {:04X} vs {:04X}",
+                self.stack_pointer, other.stack_pointer
+            ));
+        }
+        if self.error != other.error {
+            return Some(format!(
+                "the error is {:?} vs {:?}",
+                self.error, other.error
+            ));
+        }
+        if self.key_wait != other.key_wait {
+            return Some(format!(
+                "the key wait is {:?} vs {:?}",
+                self.key_wait, other.key_wait
+            ));
+        }
+        // compared as a whole first, which is much faster while they match
+        if self.bus.memory() != other.bus.memory() {
+            let memory = self.bus.memory().iter().zip(other.bus.memory());
+            if let Some((address, (a, b))) = memory.enumerate().find(|(_, (a, b))| a != b) {
+                return Some(format!(
+                    "memory at {:04X} is {:02X} vs {:02X}",
+                    address, a, b
+                ));
+            }
+        }
+        let (gpu, other_gpu) = (&self.bus.gpu, &other.bus.gpu);
+        if (gpu.width, gpu.height) != (other_gpu.width, other_gpu.height) {
+            return Some(format!(
+                "the display is {}x{} vs {}x{}",
+                gpu.width, gpu.height, other_gpu.width, other_gpu.height
+            ));
+        }
+        if let Some(y) = (0..gpu.height).find(|&y| gpu.buffer[y] != other_gpu.buffer[y]) {
+            return Some(format!("display row {} differs", y));
+        }
+        None
+    }
+
+    /// Runs the instruction at PC on its own, outside of a frame; for tests
+    #[cfg(feature = "testing")]
+    pub fn step(&mut self) {
+        self.tick();
+    }
+
     pub fn get_op(&self) -> u16 {
-        ((self.bus.read_byte(self.pc) as u16) << 8) | (self.bus.read_byte(self.pc + 1) as u16)
+        u16::from_be_bytes([
+            self.bus.read_byte(self.pc),
+            self.bus.read_byte(self.pc.wrapping_add(1)),
+        ])
     }
 
+    /// Runs one frame: up to `ticks` instructions, then a tick of the timers
     pub fn ticks(&mut self, ticks: u16) {
-        for _ in 0..ticks {
-            self.tick();
+        self.partial_frame(ticks, u32::MAX);
+    }
+
+    /// Runs a frame like `ticks`, but stops after `limit` instructions, in
+    /// which case the timers aren't updated. Returns the instructions run.
+    pub fn partial_frame(&mut self, ticks: u16, limit: u32) -> u32 {
+        // the frame boundary: a DXYN waiting for the display can go on
+        self.waiting_for_vblank = false;
+        if let Some(key) = self.held_key {
+            self.key_down(key);
+        }
+        if let Some(watch) = &mut self.quirk_watch {
+            watch.frame();
         }
+        let mut count = 0;
+        match self.timing {
+            Timing::Instructions => {
+                let blocks = self.engine == Engine::Blocks && self.quirk_watch.is_none();
+                let mut loops = LoopFinder::default();
+                let mut left = ticks as u32;
+                while left > 0 {
+                    if count == limit {
+                        return count;
+                    }
+                    let run = if blocks {
+                        let budget = left.min(limit - count);
+                        let (run, pure) = self.run_block(budget);
+                        // every round of the loop is the same until the frame
+                        // ends, so all those that fit are skipped
+                        let skipped = loops
+                            .after(pure, self.pc, &self.v, self.i, count + run)
+                            .map_or(0, |length| (budget - run) / length * length);
+                        run + skipped
+                    } else {
+                        self.tick();
+                        1
+                    };
+                    count += run;
+                    left -= run;
+                    if self.waiting_for_vblank {
+                        break;
+                    }
+                }
+            }
+            Timing::VipCycles => {
+                if !self.vip_frame(&mut count, limit) {
+                    return count;
+                }
+            }
+        }
+        self.machine_code_budget = MAX_MACHINE_CODE_CYCLES;
         self.decr_timers();
+        count
+    }
+
+    /// Runs instructions for one frame's worth of COSMAC VIP machine cycles,
+    /// or until `count` reaches `limit`; returns whether the frame is over
+    fn vip_frame(&mut self, count: &mut u32, limit: u32) -> bool {
+        // cycles overspent in the previous frame are paid back now
+        self.cycles += timing::VIP_CYCLES_PER_FRAME - timing::VIP_DISPLAY_CYCLES;
+        while self.cycles > 0 {
+            if *count == limit {
+                return false;
+            }
+            *count += 1;
+            let opcode = match self.key_wait {
+                Some(wait) => 0xf00a | (wait.x as u16) << 8,
+                None => self.get_op(),
+            };
+            let vx = self.v[X!(opcode)];
+            let pc = self.pc;
+            self.tick();
+            let skipped = self.pc == pc.wrapping_add(4);
+            let cost = timing::vip_cycles(opcode, vx, skipped) as i32
+                + std::mem::take(&mut self.machine_code_cycles) as i32;
+
+            if self.waiting_for_vblank {
+                // DXYN waits for the display interrupt, so the rest of
+                // the frame is lost and drawing is paid for in the next
+                self.cycles = -cost;
+                break;
+            }
+            self.cycles -= cost;
+        }
+        true
     }
 
     pub fn decr_timers(&mut self) {
@@ -195,279 +641,524 @@ impl Cpu {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+        if self.click_timer > 0 {
+            self.click_timer -= 1;
+        }
+    }
+
+    /// Runs the block at PC and the instruction that ends it, at most `budget`
+    /// instructions in all; returns the instructions run, and whether the
+    /// block was pure
+    fn run_block(&mut self, budget: u32) -> (u32, bool) {
+        if self.error.is_some() {
+            return (budget, false);
+        }
+        if self.key_wait.is_some() {
+            // the keys don't change within a frame, so after one look
+            // the wait can't end before the next frame
+            self.wait_for_key();
+            let run = if self.key_wait.is_some() { budget } else { 1 };
+            return (run, false);
+        }
+        let start = self.pc;
+        let block = self.blocks.take(&mut self.bus, start);
+        let run = block.straight.len().min(budget as usize);
+        for &instruction in &block.straight[..run] {
+            self.execute(instruction);
+        }
+        let (end, pure) = (block.end, block.pure);
+        self.blocks.put(start, block);
+        self.pc = self.pc.wrapping_add(2 * run as u16);
+        if run == budget as usize {
+            self.normalize_pc();
+            return (run as u32, pure);
+        }
+        match end {
+            Some(instruction) => {
+                self.pc = self.pc.wrapping_add(2);
+                self.execute(instruction);
+                self.normalize_pc();
+                (run as u32 + 1, pure)
+            }
+            // at the last byte of memory, where the instruction wraps around
+            None if run == 0 => {
+                self.tick();
+                (1, false)
+            }
+            // the block stopped short; the next one goes on from here
+            None => {
+                self.normalize_pc();
+                (run as u32, pure)
+            }
+        }
+    }
+
+    /// Keeps the program counter within memory
+    fn normalize_pc(&mut self) {
+        if self.pc as usize >= self.bus.memory().len() {
+            self.pc = (self.pc as usize % self.bus.memory().len()) as u16;
+        }
     }
 
     fn tick(&mut self) {
-        let opcode: u16 = self.get_op();
-        self.pc += 2;
-
-        match opcode & 0xf000 {
-            0x0000 => {
-                match opcode & 0xfff {
-                    0x0e0 => {
-                        // clear screen 00E0
-                        self.bus.gpu.clear();
-                    }
-                    0x0ee => {
-                        // Returning from a subroutine 00EE
-                        self.pc = self.stack.pop().unwrap();
-                    }
-                    _ => {
-                        // Execute machine language routine 0NNN
-                        // don't implement
-                    }
-                }
+        if self.error.is_some() {
+            return;
+        }
+        if self.key_wait.is_some() {
+            self.wait_for_key();
+            return;
+        }
+        if let Some(mut watch) = self.quirk_watch.take() {
+            watch.before(self, self.get_op());
+            self.quirk_watch = Some(watch);
+        }
+        let instruction = self.bus.instruction(self.pc);
+        self.pc = self.pc.wrapping_add(2);
+        self.execute(instruction);
+        self.normalize_pc();
+    }
+
+    /// Runs an instruction, with PC already past it
+    fn execute(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::ClearScreen => {
+                // clear screen 00E0
+                self.bus.gpu.clear();
+            }
+            Instruction::Return => {
+                // Returning from a subroutine 00EE
+                self.op_00EE();
+            }
+            Instruction::Lores => {
+                // Low resolution 00FE (SUPER-CHIP)
+                let (width, height) = self.bus.profile.display;
+                self.bus.gpu.set_size(width, height);
+            }
+            Instruction::Hires => {
+                // High resolution 00FF (SUPER-CHIP)
+                self.bus.gpu.set_size(gpu::HIRES.0, gpu::HIRES.1);
             }
-            0x1000 => {
+            Instruction::MachineCode(address) => {
+                // Execute machine language routine 0NNN
+                self.machine_code_cycles = self.op_0NNN(address);
+            }
+            Instruction::Jump(address) => {
                 // jump to NNN
-                self.pc = NNN!(opcode);
+                self.pc = address;
             }
-            0x2000 => {
+            Instruction::Call(address) => {
                 // 2NNN - calls the subroutine at memory location NNN
-                self.stack.push(self.pc);
-                self.pc = NNN!(opcode);
+                self.op_2NNN(address);
             }
-            0x3000 => {
+            Instruction::SkipIfEqual(x, nn) => {
                 // 3XNN - skip one instruction if the value in VX is equal to NN
-                if self.v[X!(opcode)] == NN!(opcode) {
-                    self.pc += 2;
+                if self.v[x as usize] == nn {
+                    self.skip();
                 }
             }
-            0x4000 => {
+            Instruction::SkipIfNotEqual(x, nn) => {
                 // 4XNN - skip one instruction if the value in VX is not equal to NN
-                if self.v[X!(opcode)] != NN!(opcode) {
-                    self.pc += 2;
+                if self.v[x as usize] != nn {
+                    self.skip();
                 }
             }
-            0x5000 => {
+            Instruction::SkipIfRegistersEqual(x, y) => {
                 // 5XY0 - skips if the values in VX and VY are equal
-                if self.v[X!(opcode)] == self.v[Y!(opcode)] {
-                    self.pc += 2;
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.skip();
                 }
             }
-            0x6000 => {
+            Instruction::Set(x, nn) => {
                 // 6XNN - set register VX
-                self.v[X!(opcode)] = NN!(opcode);
+                self.v[x as usize] = nn;
             }
-            0x7000 => {
+            Instruction::Add(x, nn) => {
                 // 7XNN - add value to register VX
-                self.v[X!(opcode)] = self.v[X!(opcode)].wrapping_add(NN!(opcode));
-            }
-            0x8000 => {
-                let x = X!(opcode);
-                let y = Y!(opcode);
-                match opcode & 0x000f {
-                    0x0 => {
-                        // VX is set to the value of VY 8XY0
-                        self.v[x] = self.v[y];
-                    }
-                    0x1 => {
-                        // VX is set to the bitwise OR of VX and VY
-                        self.v[x] |= self.v[y];
-                        // COSMAC VIP behavior undefined
-                        // Timendus' test suite requires consistency
-                        self.v[0xf] = 0;
-                    }
-                    0x2 => {
-                        // VX is set to the bitwise AND of VX and VY
-                        self.v[x] &= self.v[y];
-                        // COSMAC VIP behavior undefined
-                        // Timendus' test suite requires consistency
-                        self.v[0xf] = 0;
-                    }
-                    0x3 => {
-                        // VX is set to the bitwise XOR of VX and VY
-                        self.v[x] ^= self.v[y];
-                        // COSMAC VIP behavior undefined
-                        // Timendus' test suite requires consistency
-                        self.v[0xf] = 0;
-                    }
-                    0x4 => {
-                        // 8XY4 - Add VY to VX with carry
-                        let (result, carry) = self.v[x].overflowing_add(self.v[y]);
-                        self.v[x] = result;
-                        self.v[0xF] = carry as u8;
-                    }
-                    0x5 => {
-                        // 8XY5 - set VX to the result of VX - VY
-                        let flag = match self.v[x] >= self.v[y] {
-                            true => 1,
-                            false => 0,
-                        };
-                        self.v[X!(opcode)] = self.v[X!(opcode)].wrapping_sub(self.v[Y!(opcode)]);
-                        self.v[0xf] = flag;
-                    }
-                    0x6 => {
-                        // 8XY6 - Shift right with carry
-                        // original COSMAC VIP=true
-                        self.v[x] = self.v[y];
-                        let flag = self.v[x] & 0x1;
-                        self.v[x] >>= 1;
-                        self.v[0xf] = flag;
-                    }
-                    0x7 => {
-                        // 8XY7 - set VX to the result of VY - VX
-                        let flag = if self.v[y] >= self.v[x] { 1 } else { 0 };
-                        self.v[x] = self.v[y].wrapping_sub(self.v[x]);
-                        self.v[0xf] = flag;
-                    }
-                    0xe => {
-                        // 8XYE - Shift left with carry
-                        // original COSMAC VIP=true
-                        self.v[x] = self.v[y];
-                        let flag = self.v[x] >> 7;
-                        self.v[x] <<= 1;
-                        self.v[0xf] = flag;
-                    }
-                    _ => {
-                        eprintln!(">>opcode {:04x} invalid<<", opcode);
-                    }
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+            }
+            Instruction::Copy(x, y) => {
+                // VX is set to the value of VY 8XY0
+                self.v[x as usize] = self.v[y as usize];
+            }
+            Instruction::Or(x, y) => {
+                // VX is set to the bitwise OR of VX and VY
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xf] = 0;
                 }
             }
-            0x9000 => {
+            Instruction::And(x, y) => {
+                // VX is set to the bitwise AND of VX and VY
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xf] = 0;
+                }
+            }
+            Instruction::Xor(x, y) => {
+                // VX is set to the bitwise XOR of VX and VY
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xf] = 0;
+                }
+            }
+            Instruction::AddRegisters(x, y) => {
+                // 8XY4 - Add VY to VX with carry
+                let (x, y) = (x as usize, y as usize);
+                let (result, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = result;
+                self.v[0xF] = carry as u8;
+            }
+            Instruction::Subtract(x, y) => {
+                // 8XY5 - set VX to the result of VX - VY
+                let (x, y) = (x as usize, y as usize);
+                let flag = match self.v[x] >= self.v[y] {
+                    true => 1,
+                    false => 0,
+                };
+                self.v[x] = self.v[x].wrapping_sub(self.v[y]);
+                self.v[0xf] = flag;
+            }
+            Instruction::ShiftRight(x, y) => {
+                // 8XY6 - Shift right with carry
+                let (x, y) = (x as usize, y as usize);
+                if !self.quirks.shift {
+                    self.v[x] = self.v[y];
+                }
+                let flag = self.v[x] & 0x1;
+                self.v[x] >>= 1;
+                self.v[0xf] = flag;
+            }
+            Instruction::SubtractReversed(x, y) => {
+                // 8XY7 - set VX to the result of VY - VX
+                let (x, y) = (x as usize, y as usize);
+                let flag = if self.v[y] >= self.v[x] { 1 } else { 0 };
+                self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+                self.v[0xf] = flag;
+            }
+            Instruction::ShiftLeft(x, y) => {
+                // 8XYE - Shift left with carry
+                let (x, y) = (x as usize, y as usize);
+                if !self.quirks.shift {
+                    self.v[x] = self.v[y];
+                }
+                let flag = self.v[x] >> 7;
+                self.v[x] <<= 1;
+                self.v[0xf] = flag;
+            }
+            Instruction::SkipIfRegistersNotEqual(x, y) => {
                 // 9XY0 - skips if the values in VX and VY are not equal
-                if self.v[X!(opcode)] != self.v[Y!(opcode)] {
-                    self.pc += 2;
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.skip();
                 }
             }
-            0xa000 => {
+            Instruction::SetIndex(address) => {
                 // ANNN - set index register I
-                self.i = NNN!(opcode);
+                self.i = address;
             }
-            0xb000 => {
-                // BNNN Jump to NNN plus V0
-                // COSMAC VIP implementation crashes Timendus test
-                // self.pc = NNN!(opcode) + self.v[0] as u16;
-                self.pc = NNN!(opcode) + self.v[X!(opcode)] as u16;
+            Instruction::JumpWithOffset(address, x) => {
+                // BNNN Jump to NNN plus V0, or BXNN to XNN plus VX
+                let offset = if self.quirks.jump {
+                    self.v[x as usize]
+                } else {
+                    self.v[0]
+                };
+                self.pc = address + offset as u16;
             }
-            0xc000 => {
+            Instruction::Random(x, nn) => {
                 // CXNN - Random number AND NN
-                // first op to use Rust macros
-                let salt: u8 = rand::random();
-                self.v[X!(opcode)] = salt & NN!(opcode);
+                let salt: u8 = self.rng.gen();
+                self.v[x as usize] = salt & nn;
             }
-            0xd000 => {
+            Instruction::Draw(x, y, n) => {
                 // DXYN - display/draw
-                self.op_DXYN(X!(opcode), Y!(opcode), N!(opcode));
-            }
-            0xe000 => {
-                match opcode & 0xff {
-                    0x9e => {
-                        // EX9E - Skip if key VX is pressed
-                        let vx = self.v[X!(opcode)];
-                        if self.keys_down[(vx % self.keys_down.len() as u8) as usize] {
-                            self.pc += 2;
-                        }
-                    }
-                    0xa1 => {
-                        // EXA1 - Skip if key VX is not pressed
-                        let vx = self.v[X!(opcode)];
-                        if !self.keys_down[(vx % self.keys_down.len() as u8) as usize] {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => {
-                        eprintln!(">>opcode {:04x} invalid<<", opcode);
-                    }
+                self.op_DXYN(x as usize, y as usize, n);
+            }
+            Instruction::SkipIfKey(x) => {
+                // EX9E - Skip if key VX is pressed
+                let vx = self.v[x as usize];
+                if self.key_down((vx % self.keys_down.len() as u8) as usize) {
+                    self.skip();
                 }
             }
-            0xf000 => {
-                let x = ((opcode & 0x0f00) >> 8) as usize;
-                match opcode & 0xff {
-                    0x07 => {
-                        // FX07 - sets VX to the current value of the delay timer
-                        self.v[x] = self.delay_timer;
-                    }
-                    0x0a => {
-                        // FX0A - Get key
-                        self.op_FX0A(x);
-                    }
-                    0x15 => {
-                        // FX15 - sets the delay timer to the value in VX
-                        self.delay_timer = self.v[x];
-                    }
-                    0x18 => {
-                        // FX18 - sets the sound timer to the value in VX
-                        self.sound_timer = self.v[x];
-                    }
-                    0x1e => {
-                        // FX1E - Add VX to index
-                        let vx: u8 = self.v[((opcode & 0x0f00) >> 8) as usize];
-                        self.i += vx as u16;
-                    }
-                    0x29 => {
-                        // FX29 - Font character
-                        self.i = 0x0050 + (self.v[x] & 0xf) as u16;
-                    }
-                    0x33 => {
-                        // FX33 - Binary-coded decimal conversion
-                        let vx: u8 = self.v[((opcode & 0x0f00) >> 8) as usize];
-                        self.bus.save_byte(self.i, vx / 100);
-                        self.bus.save_byte(self.i + 1, vx / 10 % 10);
-                        self.bus.save_byte(self.i + 2, vx % 10);
-                    }
-                    0x55 => {
-                        // FX55 - store registers to memory
-                        // original COSMAC VIP
-                        for n in 0..x + 1 {
-                            self.bus.save_byte(self.i, self.v[n]);
-                            self.i += 1;
-                        }
-                    }
-                    0x65 => {
-                        // FX65 - load registers from memory
-                        // original COSMAC VIP
-                        for n in 0..x + 1 {
-                            self.v[n] = self.bus.read_byte(self.i);
-                            self.i += 1;
-                        }
-                    }
-                    _ => {
-                        eprintln!(">>F opcode {:04x} invalid<<", opcode);
-                    }
+            Instruction::SkipIfNotKey(x) => {
+                // EXA1 - Skip if key VX is not pressed
+                let vx = self.v[x as usize];
+                if !self.key_down((vx % self.keys_down.len() as u8) as usize) {
+                    self.skip();
+                }
+            }
+            Instruction::GetDelay(x) => {
+                // FX07 - sets VX to the current value of the delay timer
+                self.v[x as usize] = self.delay_timer;
+            }
+            Instruction::WaitForKey(x) => {
+                // FX0A - Get key
+                self.op_FX0A(x as usize);
+            }
+            Instruction::SetDelay(x) => {
+                // FX15 - sets the delay timer to the value in VX
+                self.delay_timer = self.v[x as usize];
+            }
+            Instruction::SetSound(x) => {
+                // FX18 - sets the sound timer to the value in VX
+                self.sound_timer = self.v[x as usize];
+            }
+            Instruction::AddToIndex(x) => {
+                // FX1E - Add VX to index
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
+            }
+            Instruction::Font(x) => {
+                // FX29 - Font character
+                let height = self.bus.font.small.height as u16;
+                self.i = self.bus.profile.font_address + (self.v[x as usize] & 0xf) as u16 * height;
+            }
+            Instruction::BigFont(x) => {
+                // FX30 - Big font character (SUPER-CHIP); fonts with just the
+                // digits show 9 for A to F, rather than what follows them
+                let font = self.bus.font.big;
+                let character = (self.v[x as usize] & 0xf).min(font.characters() - 1);
+                self.i = self.bus.profile.big_font_address + character as u16 * font.height as u16;
+            }
+            Instruction::Bcd(x) => {
+                // FX33 - Binary-coded decimal conversion
+                let vx = self.v[x as usize];
+                self.bus.save_byte(self.i, vx / 100);
+                self.bus.save_byte(self.i.wrapping_add(1), vx / 10 % 10);
+                self.bus.save_byte(self.i.wrapping_add(2), vx % 10);
+            }
+            Instruction::Store(x) => {
+                // FX55 - store registers to memory
+                let x = x as usize;
+                for n in 0..x + 1 {
+                    self.bus.save_byte(self.i.wrapping_add(n as u16), self.v[n]);
+                }
+                self.incr_i_after_load_store(x);
+            }
+            Instruction::Load(x) => {
+                // FX65 - load registers from memory
+                let x = x as usize;
+                for n in 0..x + 1 {
+                    self.v[n] = self.bus.read_byte(self.i.wrapping_add(n as u16));
+                }
+                self.incr_i_after_load_store(x);
+            }
+            Instruction::Invalid(_) => {
+                // reported once, when decoded
+            }
+        };
+    }
+
+    /// FX55 and FX65 move I past the registers, unless a quirk says otherwise
+    fn incr_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+        let count = if self.quirks.memory_increment_by_x {
+            x
+        } else {
+            x + 1
+        };
+        self.i = self.i.wrapping_add(count as u16);
+    }
+
+    /// Skips the next instruction
+    fn skip(&mut self) {
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    #[allow(non_snake_case)]
+    /// 0NNN - runs the RCA 1802 routine at NNN until it returns to the interpreter
+    /// with SEP R4, and returns the machine cycles it took. The interpreter's state
+    /// is mapped into memory where the COSMAC VIP interpreter keeps it.
+    fn op_0NNN(&mut self, address: u16) -> u32 {
+        if !self.bus.profile.machine_code {
+            return 0;
+        }
+        let vip_display = (self.bus.gpu.width, self.bus.gpu.height) == gpu::LORES;
+
+        for (n, &vn) in self.v.iter().enumerate() {
+            self.bus.save_byte(vip::REGISTERS + n as u16, vn);
+        }
+        if vip_display {
+            for y in 0..self.bus.gpu.height {
+                let bytes = ((self.bus.gpu.buffer[y] >> 64) as u64).to_be_bytes();
+                for (x, &byte) in bytes.iter().enumerate() {
+                    self.bus.save_byte(vip::DISPLAY + (y * 8 + x) as u16, byte);
                 }
             }
-            _ => {
-                panic!(">>opcode {:04x} invalid<<", opcode);
+        }
+
+        // R2 is the stack, R3 the program counter, R5 the CHIP-8 program
+        // counter, RA is I and RB points at the display
+        let cpu = &mut self.cdp1802;
+        cpu.r[2] = match self.stack_overflow {
+            StackOverflow::Memory => self.stack_pointer,
+            _ => self.bus.profile.stack_address,
+        };
+        cpu.r[3] = address;
+        cpu.r[5] = self.pc;
+        cpu.r[0xa] = self.i;
+        cpu.r[0xb] = vip::DISPLAY;
+        cpu.x = 2;
+        cpu.p = 3;
+        cpu.idle = false;
+
+        let mut cycles = 0;
+        let failure = loop {
+            if cpu.p == 4 {
+                break None;
+            }
+            if cpu.idle && !cpu.ie {
+                // nothing can wake it up
+                break Some("went idle with interrupts off");
+            }
+            cycles += cpu.step(&mut self.bus);
+            if cycles > self.machine_code_budget {
+                break Some("did not return");
             }
         };
+        self.machine_code_budget = self.machine_code_budget.saturating_sub(cycles);
+        if let Some(failure) = failure {
+            let at = self.pc.wrapping_sub(2);
+            log::error!("Machine code at {:04X} {}", address, failure);
+            self.stop(at, format!("Machine code at {:04X} {}", address, failure));
+        }
+
+        for n in 0..self.v.len() {
+            self.v[n] = self.bus.read_byte(vip::REGISTERS + n as u16);
+        }
+        self.i = self.cdp1802.r[0xa];
+        if vip_display {
+            for y in 0..self.bus.gpu.height {
+                let mut bytes = [0; 8];
+                for (x, byte) in bytes.iter_mut().enumerate() {
+                    *byte = self.bus.read_byte(vip::DISPLAY + (y * 8 + x) as u16);
+                }
+                self.bus.gpu.buffer[y] = (u64::from_be_bytes(bytes) as u128) << 64;
+            }
+            self.bus.gpu.has_changed = true;
+        }
+        cycles
     }
 
     #[allow(non_snake_case)]
     // DXYN - display/draw
     fn op_DXYN(&mut self, x: usize, y: usize, n: u8) {
-        let x_coord = self.v[x] & 0x3f;
-        let mut y_coord = self.v[y] & 0x1f;
-        self.v[0xf] = 0;
-        for address in self.i..self.i + n as u16 {
-            if self.bus.display(x_coord, y_coord, address) {
-                self.v[0xf] = 1;
+        // the start position always wraps; the rest of the sprite may be clipped
+        let x_coord = self.v[x] as usize % self.bus.gpu.width;
+        let y_coord = self.v[y] as usize % self.bus.gpu.height;
+        let hits = if n == 0 && !self.quirks.big_sprites && !self.bus.gpu.is_hires() {
+            // a sprite of no rows, as on the VIP: nothing is drawn or hit
+            gpu::SpriteHits::default()
+        } else {
+            self.bus
+                .draw_sprite(x_coord, y_coord, self.i, n, self.quirks.wrap)
+        };
+        self.v[0xf] = if self.quirks.row_collisions && self.bus.gpu.is_hires() {
+            hits.collided_rows + hits.clipped_rows
+        } else {
+            (hits.collided_rows > 0) as u8
+        };
+        // the sprite is on screen; nothing more runs until the next frame. The
+        // VIP always waits for the display interrupt; elsewhere it's a quirk.
+        if self.quirks.vblank || self.timing == Timing::VipCycles {
+            self.waiting_for_vblank = true;
+        }
+    }
+
+    #[allow(non_snake_case)]
+    fn op_00EE(&mut self) {
+        let at = self.pc.wrapping_sub(2);
+        if self.stack_overflow == StackOverflow::Memory {
+            // an empty stack returns to whatever is above it, as on the VIP
+            let high = self.bus.read_byte(self.stack_pointer.wrapping_add(1));
+            let low = self.bus.read_byte(self.stack_pointer.wrapping_add(2));
+            self.stack_pointer = self.stack_pointer.wrapping_add(2);
+            self.pc = u16::from_be_bytes([high, low]);
+            return;
+        }
+        match self.stack.pop() {
+            Some(address) => self.pc = address,
+            None => {
+                log::error!("Return at {:04X} without a call", at);
+                if self.stack_overflow == StackOverflow::Error {
+                    self.stop(at, format!("Return at {:04X} without a call", at));
+                }
             }
-            y_coord += 1;
         }
     }
 
+    #[allow(non_snake_case)]
+    fn op_2NNN(&mut self, address: u16) {
+        let at = self.pc.wrapping_sub(2);
+        match self.stack_overflow {
+            StackOverflow::Memory => {
+                let [high, low] = self.pc.to_be_bytes();
+                self.bus.save_byte(self.stack_pointer, low);
+                self.bus.save_byte(self.stack_pointer.wrapping_sub(1), high);
+                self.stack_pointer = self.stack_pointer.wrapping_sub(2);
+            }
+            _ if self.stack.len() >= self.stack_depth => {
+                log::error!("Stack overflow at {:04X}", at);
+                if self.stack_overflow == StackOverflow::Error {
+                    self.stop(at, format!("Stack overflow at {:04X}", at));
+                    return;
+                }
+                let excess = self.stack.len() + 1 - self.stack_depth.max(1);
+                self.stack.drain(..excess);
+                self.stack.push(self.pc);
+            }
+            _ => self.stack.push(self.pc),
+        }
+        self.pc = address;
+    }
+
+    /// Stops the program at the instruction at `at`
+    fn stop(&mut self, at: u16, error: String) {
+        self.pc = at;
+        self.error = Some(error);
+    }
+
     #[allow(non_snake_case)]
     /// Fx0A GETKEY
     fn op_FX0A(&mut self, x: usize) {
-        self.pc -= 2;
-        match self.key_pressed {
-            None => {
-                self.key_pressed = self
-                    .keys_down
-                    .iter()
-                    .enumerate()
-                    .find_map(|(index, &value)| value.then_some(index));
-            }
-            Some(key) => {
-                if self.keys_down[key] {
-                    self.sound_timer = 4;
-                } else if self.sound_timer == 0 {
-                    self.pc += 2;
-                    self.v[x] = key as u8;
-                    self.key_pressed = None;
+        self.key_wait = Some(KeyWait { x, pressed: None });
+        self.wait_for_key();
+    }
+
+    /// Looks at the keys for FX0A: a key pressed, and then released unless
+    /// the quirk says a press is enough
+    fn wait_for_key(&mut self) {
+        let Some(mut wait) = self.key_wait else {
+            return;
+        };
+        if wait.pressed.is_none() {
+            wait.pressed = (0..self.keys_down.len()).find(|&key| self.key_down(key));
+            if wait.pressed.is_some() && self.key_click {
+                self.click_timer = CLICK_FRAMES;
+            }
+        }
+        self.key_wait = match wait.pressed {
+            Some(key) if self.quirks.key_wait_press || !self.keys_down[key] => {
+                if self.quirks.key_wait_press {
+                    self.held_key = Some(key);
                 }
+                self.v[wait.x] = key as u8;
+                None
             }
+            _ => Some(wait),
         };
     }
+
+    /// Whether `key` is down; the key FX0A took at its press reads as up
+    /// until it is released
+    fn key_down(&mut self, key: usize) -> bool {
+        if self.held_key == Some(key) {
+            if self.keys_down[key] {
+                return false;
+            }
+            self.held_key = None;
+        }
+        self.keys_down[key]
+    }
 }
@@ -0,0 +1,224 @@
+/// A set of hex digit sprites, as built into the various CHIP-8 interpreters
+#[derive(Debug, PartialEq, Eq)]
+pub struct Font {
+    pub name: &'static str,
+    /// Rows per character; small fonts are 5 rows high, big fonts 10
+    pub height: u8,
+    /// Sprite data for the characters 0 to F, or just 0 to 9 for some big fonts
+    pub glyphs: &'static [u8],
+}
+
+impl Font {
+    /// The characters the font has sprites for: 16, or 10 for the digits only
+    pub fn characters(&self) -> u8 {
+        (self.glyphs.len() / self.height as usize) as u8
+    }
+}
+
+#[rustfmt::skip]
+pub const VIP: Font = Font {
+    name: "COSMAC VIP",
+    height: 5,
+    glyphs: &[
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        0x60, 0x20, 0x20, 0x20, 0x70, // 1
+        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+    ],
+};
+
+#[rustfmt::skip]
+pub const DREAM_6800: Font = Font {
+    name: "DREAM 6800",
+    height: 5,
+    glyphs: &[
+        0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+        0x40, 0x40, 0x40, 0x40, 0x40, // 1
+        0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+        0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+        0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+        0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+        0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+        0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+        0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+        0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+        0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+        0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+        0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+        0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+    ],
+};
+
+#[rustfmt::skip]
+pub const ETI_660: Font = Font {
+    name: "ETI-660",
+    height: 5,
+    glyphs: &[
+        0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+        0x20, 0x20, 0x20, 0x20, 0x20, // 1
+        0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+        0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+        0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+        0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+        0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+        0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+        0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+        0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+        0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+        0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+        0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+        0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+    ],
+};
+
+#[rustfmt::skip]
+pub const OCTO: Font = Font {
+    name: "Octo",
+    height: 5,
+    glyphs: &[
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        0x20, 0x60, 0x20, 0x20, 0x70, // 1
+        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+    ],
+};
+
+#[rustfmt::skip]
+pub const FISH_N_CHIPS: Font = Font {
+    name: "fish'n'chips",
+    height: 5,
+    glyphs: &[
+        0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+        0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+        0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+        0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+        0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+        0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+        0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+        0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+        0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+        0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+        0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+        0x60, 0x80, 0x80, 0x80, 0x60, // C
+        0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+        0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+    ],
+};
+
+/// The SUPER-CHIP 1.1 big font only has the digits 0 to 9
+#[rustfmt::skip]
+pub const SCHIP_BIG: Font = Font {
+    name: "SUPER-CHIP",
+    height: 10,
+    glyphs: &[
+        0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+        0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+        0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+        0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+        0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+        0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+        0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    ],
+};
+
+/// Octo extends the big font with the characters A to F
+#[rustfmt::skip]
+pub const OCTO_BIG: Font = Font {
+    name: "Octo",
+    height: 10,
+    glyphs: &[
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ],
+};
+
+pub const SMALL_FONTS: [&Font; 5] = [&VIP, &DREAM_6800, &ETI_660, &OCTO, &FISH_N_CHIPS];
+
+pub const BIG_FONTS: [&Font; 2] = [&SCHIP_BIG, &OCTO_BIG];
+
+/// The small and big font loaded into the interpreter area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontSet {
+    pub small: &'static Font,
+    pub big: &'static Font,
+}
+
+impl Default for FontSet {
+    fn default() -> Self {
+        Self {
+            small: &OCTO,
+            big: &SCHIP_BIG,
+        }
+    }
+}
+
+impl FontSet {
+    /// Looks up a `fontStyle` value from the ROM database. The DREAM 6800
+    /// and ETI-660 fonts aren't styles of it; they come with their machines,
+    /// or from the Font menu.
+    pub fn from_style(style: &str) -> Option<Self> {
+        match style {
+            "octo" => Some(Self {
+                small: &OCTO,
+                big: &OCTO_BIG,
+            }),
+            "fish" => Some(Self {
+                small: &FISH_N_CHIPS,
+                big: &OCTO_BIG,
+            }),
+            "vip" => Some(Self {
+                small: &VIP,
+                big: &SCHIP_BIG,
+            }),
+            "schip" => Some(Self::default()),
+            _ => None,
+        }
+    }
+}
@@ -1,41 +1,135 @@
-/// the screen is 64 pixels wide x 32 pixels high
-#[derive(Debug, Clone, Copy)]
+/// The widest screen supported, in pixels; one row fits in a `u128`
+pub const MAX_WIDTH: usize = 128;
+
+/// The screen is 64 pixels wide x 32 pixels high
+pub const LORES: (usize, usize) = (64, 32);
+
+/// SUPER-CHIP's high resolution mode is 128 pixels wide x 64 pixels high
+pub const HIRES: (usize, usize) = (128, 64);
+
+/// What happened while drawing a sprite
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpriteHits {
+    /// Rows in which a pixel was flipped back to 0
+    pub collided_rows: u8,
+    /// Rows that fell off the bottom of the screen
+    pub clipped_rows: u8,
+}
+
+#[derive(Debug)]
 pub struct Gpu {
-    pub buffer: [u64; 32],
+    /// One row per line, with the leftmost pixel in the most significant bit
+    pub buffer: Vec<u128>,
+    pub width: usize,
+    pub height: usize,
     pub has_changed: bool,
 }
 
+impl Clone for Gpu {
+    fn clone(&self) -> Self {
+        Gpu {
+            buffer: self.buffer.clone(),
+            width: self.width,
+            height: self.height,
+            has_changed: self.has_changed,
+        }
+    }
+
+    /// Copies `source` into the rows this display already has
+    fn clone_from(&mut self, source: &Self) {
+        self.buffer.clone_from(&source.buffer);
+        self.width = source.width;
+        self.height = source.height;
+        self.has_changed = source.has_changed;
+    }
+}
+
+impl Default for Gpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Gpu {
     pub fn new() -> Self {
         Gpu {
-            buffer: [0; 32],
+            buffer: vec![0; LORES.1],
+            width: LORES.0,
+            height: LORES.1,
             has_changed: true,
         }
     }
 
     pub fn clear(&mut self) {
-        for i in 0..32 {
-            self.buffer[i] = 0;
-        }
+        self.buffer.iter_mut().for_each(|row| *row = 0);
+        self.has_changed = true;
     }
 
-    pub fn draw_sprite_line(&mut self, x: u8, y: u8, sprite_data: u8) -> bool {
-        if y > 31 {
-            return false;
-        }
+    /// Switches the resolution, which also clears the screen
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.width = width.min(MAX_WIDTH);
+        self.height = height;
+        self.buffer = vec![0; height];
+        self.has_changed = true;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        (self.width, self.height) == HIRES
+    }
 
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.buffer[y] & (1 << (MAX_WIDTH - 1 - x)) != 0
+    }
+
+    /// Draws a sprite of `bits` wide rows at (x, y), which must be on screen.
+    /// Pixels past the right or bottom edge are clipped, or wrap around to the
+    /// other side of the screen when `wrap` is set.
+    pub fn draw_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        rows: &[u16],
+        bits: usize,
+        wrap: bool,
+    ) -> SpriteHits {
+        let mut hits = SpriteHits::default();
         self.has_changed = true;
 
-        // shift the sprite to the desired x coordinate
-        let mask = if x <= 56 {
-            (sprite_data as u64) << (56 - x)
-        } else {
-            (sprite_data as u64) >> (x - 56)
-        };
-        let cleared_any = self.buffer[y as usize] & mask > 0;
-        self.buffer[y as usize] ^= mask;
-
-        // return true if any bit was flipped back to 0
-        cleared_any
+        for (offset, &data) in rows.iter().enumerate() {
+            let mut row = y + offset;
+            if row >= self.height {
+                if !wrap {
+                    hits.clipped_rows += 1;
+                    continue;
+                }
+                row %= self.height;
+            }
+
+            let mask = self.row_mask(x, data, bits, wrap);
+            if self.buffer[row] & mask != 0 {
+                hits.collided_rows += 1;
+            }
+            self.buffer[row] ^= mask;
+        }
+        hits
+    }
+
+    /// Places the pixels of one sprite row on a screen row
+    fn row_mask(&self, x: usize, data: u16, bits: usize, wrap: bool) -> u128 {
+        let mut mask = 0;
+        for bit in 0..bits {
+            if data & (1 << (bits - 1 - bit)) == 0 {
+                continue;
+            }
+            let mut col = x + bit;
+            if col >= self.width {
+                if !wrap {
+                    break;
+                }
+                col %= self.width;
+            }
+            mask |= 1 << (MAX_WIDTH - 1 - col);
+        }
+        mask
     }
 }
@@ -0,0 +1,147 @@
+/// A decoded instruction, with its operands taken out of the opcode.
+/// X and Y are register numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 00FE (SUPER-CHIP)
+    Lores,
+    /// 00FF (SUPER-CHIP)
+    Hires,
+    /// 0NNN
+    MachineCode(u16),
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipIfEqual(u8, u8),
+    /// 4XNN
+    SkipIfNotEqual(u8, u8),
+    /// 5XY0
+    SkipIfRegistersEqual(u8, u8),
+    /// 6XNN
+    Set(u8, u8),
+    /// 7XNN
+    Add(u8, u8),
+    /// 8XY0
+    Copy(u8, u8),
+    /// 8XY1
+    Or(u8, u8),
+    /// 8XY2
+    And(u8, u8),
+    /// 8XY3
+    Xor(u8, u8),
+    /// 8XY4
+    AddRegisters(u8, u8),
+    /// 8XY5
+    Subtract(u8, u8),
+    /// 8XY6
+    ShiftRight(u8, u8),
+    /// 8XY7
+    SubtractReversed(u8, u8),
+    /// 8XYE
+    ShiftLeft(u8, u8),
+    /// 9XY0
+    SkipIfRegistersNotEqual(u8, u8),
+    /// ANNN
+    SetIndex(u16),
+    /// BNNN, or BXNN with the jump quirk
+    JumpWithOffset(u16, u8),
+    /// CXNN
+    Random(u8, u8),
+    /// DXYN
+    Draw(u8, u8, u8),
+    /// EX9E
+    SkipIfKey(u8),
+    /// EXA1
+    SkipIfNotKey(u8),
+    /// FX07
+    GetDelay(u8),
+    /// FX0A
+    WaitForKey(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddToIndex(u8),
+    /// FX29
+    Font(u8),
+    /// FX30 (SUPER-CHIP)
+    BigFont(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX55
+    Store(u8),
+    /// FX65
+    Load(u8),
+    /// An opcode the interpreter doesn't know
+    Invalid(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Self {
+        use Instruction::*;
+
+        let x = (opcode >> 8 & 0xf) as u8;
+        let y = (opcode >> 4 & 0xf) as u8;
+        let n = (opcode & 0xf) as u8;
+        let nn = (opcode & 0xff) as u8;
+        let nnn = opcode & 0xfff;
+        match opcode >> 12 {
+            0x0 => match nnn {
+                0x0e0 => ClearScreen,
+                0x0ee => Return,
+                0x0fe => Lores,
+                0x0ff => Hires,
+                _ => MachineCode(nnn),
+            },
+            0x1 => Jump(nnn),
+            0x2 => Call(nnn),
+            0x3 => SkipIfEqual(x, nn),
+            0x4 => SkipIfNotEqual(x, nn),
+            // the last nibble of 5XY0 and 9XY0 isn't looked at
+            0x5 => SkipIfRegistersEqual(x, y),
+            0x6 => Set(x, nn),
+            0x7 => Add(x, nn),
+            0x8 => match n {
+                0x0 => Copy(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddRegisters(x, y),
+                0x5 => Subtract(x, y),
+                0x6 => ShiftRight(x, y),
+                0x7 => SubtractReversed(x, y),
+                0xe => ShiftLeft(x, y),
+                _ => Invalid(opcode),
+            },
+            0x9 => SkipIfRegistersNotEqual(x, y),
+            0xa => SetIndex(nnn),
+            0xb => JumpWithOffset(nnn, x),
+            0xc => Random(x, nn),
+            0xd => Draw(x, y, n),
+            0xe => match nn {
+                0x9e => SkipIfKey(x),
+                0xa1 => SkipIfNotKey(x),
+                _ => Invalid(opcode),
+            },
+            _ => match nn {
+                0x07 => GetDelay(x),
+                0x0a => WaitForKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1e => AddToIndex(x),
+                0x29 => Font(x),
+                0x30 => BigFont(x),
+                0x33 => Bcd(x),
+                0x55 => Store(x),
+                0x65 => Load(x),
+                _ => Invalid(opcode),
+            },
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use super::gpu::Gpu;
+
+/// An emulated machine, as driven by the frontend: either the CHIP-8
+/// interpreter, or a whole COSMAC VIP running the original one
+pub trait Machine {
+    /// Runs one 60Hz frame; `ticks` is the number of instructions per frame,
+    /// for machines that don't keep their own time
+    fn run_frame(&mut self, ticks: u16);
+
+    /// Sets which of the 16 hex keys are held down
+    fn set_keys(&mut self, keys_down: [bool; 16]);
+
+    /// Whether the buzzer sounds
+    fn is_beeping(&self) -> bool;
+
+    fn gpu(&mut self) -> &mut Gpu;
+}
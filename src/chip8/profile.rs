@@ -0,0 +1,191 @@
+use super::font::{self, FontSet};
+use super::gpu;
+
+/// Memory layout, keypad, font and display of a machine that runs CHIP-8 programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineProfile {
+    pub name: &'static str,
+    /// Size of the memory in bytes; addresses wrap around at this size
+    pub memory_size: usize,
+    /// Where programs are loaded, and where execution starts
+    pub program_start: u16,
+    /// Where the small font is loaded, used by FX29
+    pub font_address: u16,
+    /// Where the big font is loaded, used by FX30
+    pub big_font_address: u16,
+    /// 0NNN calls RCA 1802 machine code, like on the COSMAC VIP
+    pub machine_code: bool,
+    /// The font built into the interpreter
+    pub font: FontSet,
+    /// Width and height of the display in pixels, in low resolution
+    pub display: (usize, usize),
+    pub keypad: Keypad,
+    /// Return addresses the interpreter's stack holds
+    pub stack_depth: usize,
+    /// Where a stack kept in memory starts, growing down
+    pub stack_address: u16,
+}
+
+/// The hex keypads the machines came with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keypad {
+    /// 1 2 3 C on top, as on the COSMAC VIP
+    Vip,
+    /// C D E F on top, counting up from 0 at the bottom left
+    Dream6800,
+}
+
+impl Keypad {
+    /// The keys as laid out on the keypad, row by row
+    pub fn layout(self) -> [[usize; 4]; 4] {
+        match self {
+            Keypad::Vip => [
+                [0x1, 0x2, 0x3, 0xC],
+                [0x4, 0x5, 0x6, 0xD],
+                [0x7, 0x8, 0x9, 0xE],
+                [0xA, 0x0, 0xB, 0xF],
+            ],
+            Keypad::Dream6800 => [
+                [0xC, 0xD, 0xE, 0xF],
+                [0x8, 0x9, 0xA, 0xB],
+                [0x4, 0x5, 0x6, 0x7],
+                [0x0, 0x1, 0x2, 0x3],
+            ],
+        }
+    }
+}
+
+/// Where the COSMAC VIP interpreter keeps its state, in the top pages of a 4Kb
+/// VIP; machine code routines expect to find it there
+pub mod vip {
+    /// The call stack, growing down from 0xECF
+    pub const STACK: u16 = 0x0ECF;
+    /// V0-VF
+    pub const REGISTERS: u16 = 0x0EF0;
+    /// The display, 8 bytes for each of the 32 rows
+    pub const DISPLAY: u16 = 0x0F00;
+}
+
+/// The common layout: 4Kb, with the font in the (unused) interpreter area
+pub const CHIP_8: MachineProfile = MachineProfile {
+    name: "CHIP-8 (4Kb)",
+    memory_size: 0x1000,
+    program_start: 0x200,
+    // it’s become popular to put it at 050–09F
+    font_address: 0x50,
+    big_font_address: 0xA0,
+    machine_code: false,
+    font: FontSet {
+        small: &font::OCTO,
+        big: &font::SCHIP_BIG,
+    },
+    display: gpu::LORES,
+    keypad: Keypad::Vip,
+    stack_depth: 16,
+    // below the program, above the fonts
+    stack_address: 0x1FF,
+};
+
+/// COSMAC VIP programs that use the font in the VIP's monitor ROM
+pub const VIP_HYBRID: MachineProfile = MachineProfile {
+    name: "COSMAC VIP hybrid",
+    memory_size: 0x10000,
+    program_start: 0x200,
+    font_address: 0x8110,
+    big_font_address: 0x8160,
+    machine_code: true,
+    font: FontSet {
+        small: &font::VIP,
+        big: &font::SCHIP_BIG,
+    },
+    display: gpu::LORES,
+    keypad: Keypad::Vip,
+    // the original interpreter's stack, at 0EA0-0ECF, holds 12 levels
+    stack_depth: 12,
+    stack_address: vip::STACK,
+};
+
+/// The DREAM 6800 ran CHIP-8 under its CHIPOS monitor, with its own keypad and font
+pub const DREAM_6800: MachineProfile = MachineProfile {
+    name: "DREAM 6800",
+    memory_size: 0x1000,
+    program_start: 0x200,
+    font_address: 0x50,
+    big_font_address: 0xA0,
+    machine_code: false,
+    font: FontSet {
+        small: &font::DREAM_6800,
+        big: &font::SCHIP_BIG,
+    },
+    display: gpu::LORES,
+    keypad: Keypad::Dream6800,
+    stack_depth: 16,
+    stack_address: 0x1FF,
+};
+
+/// The ETI-660 loads programs above its interpreter, at 0x600, and has
+/// a 64x48 display
+pub const ETI_660: MachineProfile = MachineProfile {
+    name: "ETI-660",
+    memory_size: 0x1000,
+    program_start: 0x600,
+    font_address: 0x50,
+    big_font_address: 0xA0,
+    machine_code: false,
+    font: FontSet {
+        small: &font::ETI_660,
+        big: &font::SCHIP_BIG,
+    },
+    display: (64, 48),
+    keypad: Keypad::Vip,
+    stack_depth: 16,
+    // at the top of the interpreter's memory, below the program
+    stack_address: 0x5FF,
+};
+
+/// The ETI-660 in its 64x64 display mode
+pub const ETI_660_64: MachineProfile = MachineProfile {
+    name: "ETI-660 (64x64)",
+    display: (64, 64),
+    ..ETI_660
+};
+
+/// XO-CHIP has 64Kb of memory
+pub const XO_CHIP: MachineProfile = MachineProfile {
+    name: "XO-CHIP (64Kb)",
+    memory_size: 0x10000,
+    program_start: 0x200,
+    font_address: 0x50,
+    big_font_address: 0xA0,
+    machine_code: false,
+    font: FontSet {
+        small: &font::OCTO,
+        big: &font::OCTO_BIG,
+    },
+    display: gpu::LORES,
+    keypad: Keypad::Vip,
+    stack_depth: 16,
+    stack_address: 0x1FF,
+};
+
+pub const PROFILES: [MachineProfile; 6] =
+    [CHIP_8, VIP_HYBRID, DREAM_6800, ETI_660, ETI_660_64, XO_CHIP];
+
+impl Default for MachineProfile {
+    fn default() -> Self {
+        CHIP_8
+    }
+}
+
+impl MachineProfile {
+    /// Picks the machine for a platform id from the ROM database. The bundled
+    /// database has no platforms for the DREAM 6800 and the ETI-660, so those
+    /// machines are only picked from the Platform menu.
+    pub fn for_platform(platform: &str) -> Self {
+        match platform {
+            "hybridVIP" => VIP_HYBRID,
+            "xochip" => XO_CHIP,
+            _ => CHIP_8,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use super::cpu::Cpu;
+use crate::{N, NNN, X, Y};
+use std::collections::BTreeMap;
+
+/// A quirk the program ran into, with the first time it made a difference
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub count: u32,
+    pub first_pc: u16,
+    /// What happened, and what would have happened under the other setting
+    pub detail: String,
+}
+
+/// Watches the running program for instructions whose outcome depends on a
+/// quirk, to tell which quirks a program relies on
+#[derive(Debug, Clone, Default)]
+pub struct QuirkWatch {
+    /// Findings by quirk id, as in the ROM database; "memory" stands for
+    /// both load/store quirks
+    pub findings: BTreeMap<&'static str, Finding>,
+    /// Set by FX55/FX65 until I is read or replaced: (pc, x, i before)
+    load_store: Option<(u16, usize, u16)>,
+    /// Set by 8XY1/2/3 until VF is read or written: (pc, VF before)
+    logic: Option<(u16, u8)>,
+    /// Sprites drawn in the current frame
+    sprites: u32,
+}
+
+impl QuirkWatch {
+    fn report(&mut self, quirk: &'static str, pc: u16, detail: impl FnOnce() -> String) {
+        self.findings
+            .entry(quirk)
+            .and_modify(|finding| finding.count += 1)
+            .or_insert_with(|| Finding {
+                count: 1,
+                first_pc: pc,
+                detail: detail(),
+            });
+    }
+
+    /// Called at every 60Hz frame boundary
+    pub fn frame(&mut self) {
+        self.sprites = 0;
+    }
+
+    /// Called before the instruction at `cpu.pc` runs
+    pub fn before(&mut self, cpu: &Cpu, opcode: u16) {
+        let pc = cpu.pc;
+        let v = cpu.v();
+        let (x, y) = (X!(opcode), Y!(opcode));
+        let quirks = cpu.quirks;
+
+        if let Some((store_pc, store_x, i_before)) = self.load_store {
+            if reads_i(opcode) {
+                let increment = |by: usize| i_before.wrapping_add(by as u16);
+                self.report("memory", pc, || {
+                    format!(
+                        "I is {:04X} at {:04X}, after FX55/FX65 at {:04X}; it would be \
+                         {:04X} incremented by X + 1, {:04X} by X, or {:04X} unchanged",
+                        cpu.i(),
+                        pc,
+                        store_pc,
+                        increment(store_x + 1),
+                        increment(store_x),
+                        i_before
+                    )
+                });
+            }
+            if reads_i(opcode) || writes_i(opcode) {
+                self.load_store = None;
+            }
+        }
+
+        if let Some((logic_pc, vf_before)) = self.logic {
+            if reads_vf(opcode) {
+                let (now, other) = if quirks.logic {
+                    (0, vf_before)
+                } else {
+                    (vf_before, 0)
+                };
+                if now != other {
+                    self.report("logic", pc, || {
+                        format!(
+                            "VF is read at {:04X} after a logic op at {:04X}; it is {:02X}, \
+                             and would be {:02X} with the vF reset quirk {}",
+                            pc,
+                            logic_pc,
+                            now,
+                            other,
+                            if quirks.logic { "off" } else { "on" }
+                        )
+                    });
+                }
+            }
+            if reads_vf(opcode) || writes_vf(opcode) {
+                self.logic = None;
+            }
+        }
+
+        match opcode & 0xf000 {
+            0x8000 => match opcode & 0xf {
+                0x1..=0x3 if x != 0xf => self.logic = Some((pc, v[0xf])),
+                0x6 | 0xe if x != y && v[x] != v[y] => {
+                    let (used, other) = if quirks.shift { (x, y) } else { (y, x) };
+                    self.report("shift", pc, || {
+                        format!(
+                            "{:04X} at {:04X} shifts V{:X} = {:02X}; with the shift quirk {} \
+                             it would shift V{:X} = {:02X}",
+                            opcode,
+                            pc,
+                            used,
+                            v[used],
+                            if quirks.shift { "off" } else { "on" },
+                            other,
+                            v[other]
+                        )
+                    });
+                }
+                _ => (),
+            },
+            0xb000 if x != 0 && v[x] != v[0] => {
+                let nnn = NNN!(opcode);
+                let (now, other) = if quirks.jump {
+                    (v[x], v[0])
+                } else {
+                    (v[0], v[x])
+                };
+                self.report("jump", pc, || {
+                    format!(
+                        "{:04X} at {:04X} jumps to {:04X}; with the jump quirk {} it would \
+                         jump to {:04X}",
+                        opcode,
+                        pc,
+                        nnn + now as u16,
+                        if quirks.jump { "off" } else { "on" },
+                        nnn + other as u16
+                    )
+                });
+            }
+            0xd000 => {
+                let gpu = &cpu.bus.gpu;
+                let (width, height) = if N!(opcode) != 0 {
+                    (8, N!(opcode))
+                } else if quirks.big_sprites || gpu.is_hires() {
+                    (16, 16)
+                } else {
+                    // nothing is drawn
+                    (0, 0)
+                };
+                let left = v[x] as usize % gpu.width;
+                let top = v[y] as usize % gpu.height;
+                if left + width > gpu.width || top + height as usize > gpu.height {
+                    self.report("wrap", pc, || {
+                        format!(
+                            "A sprite at {},{} drawn at {:04X} crosses the edge of the screen; \
+                             it is {}, and would be {}",
+                            left,
+                            top,
+                            pc,
+                            if quirks.wrap { "wrapped" } else { "clipped" },
+                            if quirks.wrap { "clipped" } else { "wrapped" }
+                        )
+                    });
+                }
+
+                self.sprites += 1;
+                if quirks.vblank || self.sprites > 1 {
+                    let sprites = self.sprites;
+                    self.report("vblank", pc, || {
+                        if quirks.vblank {
+                            format!(
+                                "DXYN at {:04X} waits for the next frame; without the vblank \
+                                 quirk the program would run on",
+                                pc
+                            )
+                        } else {
+                            format!(
+                                "{} sprites are drawn in one frame, at {:04X}; with the vblank \
+                                 quirk only one would be",
+                                sprites, pc
+                            )
+                        }
+                    });
+                }
+            }
+            0xf000 if matches!(opcode & 0xff, 0x55 | 0x65) => {
+                self.load_store = Some((pc, x, cpu.i()));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Instructions that use the value of I
+fn reads_i(opcode: u16) -> bool {
+    match opcode & 0xf000 {
+        0xd000 => true,
+        0xf000 => matches!(opcode & 0xff, 0x1e | 0x33 | 0x55 | 0x65),
+        _ => false,
+    }
+}
+
+/// Instructions that replace I without reading it
+fn writes_i(opcode: u16) -> bool {
+    match opcode & 0xf000 {
+        0xa000 => true,
+        0xf000 => opcode == 0xf000 || matches!(opcode & 0xff, 0x29 | 0x30),
+        _ => false,
+    }
+}
+
+/// Instructions that use the value of VF
+fn reads_vf(opcode: u16) -> bool {
+    let (x, y) = (X!(opcode), Y!(opcode));
+    match opcode & 0xf000 {
+        0x3000 | 0x4000 | 0x7000 | 0xe000 => x == 0xf,
+        0x5000 | 0x9000 | 0xd000 => x == 0xf || y == 0xf,
+        // 8XY0 only copies VY into VX
+        0x8000 => y == 0xf || (x == 0xf && opcode & 0xf != 0),
+        // FX55 stores VF when X is F
+        0xf000 => match opcode & 0xff {
+            0x15 | 0x18 | 0x1e | 0x29 | 0x30 | 0x33 => x == 0xf,
+            0x55 => x == 0xf,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Instructions that overwrite VF without reading it
+fn writes_vf(opcode: u16) -> bool {
+    let x = X!(opcode);
+    match opcode & 0xf000 {
+        0x6000 | 0xc000 => x == 0xf,
+        // arithmetic and drawing set VF as a flag
+        0x8000 => matches!(opcode & 0xf, 0x4..=0x7 | 0xe) || x == 0xf,
+        0xd000 => true,
+        0xf000 => matches!(opcode & 0xff, 0x07 | 0x0a | 0x65) && x == 0xf,
+        _ => false,
+    }
+}
@@ -0,0 +1,96 @@
+/// Behaviours that differ between CHIP-8 interpreters, named after the
+/// quirks in the ROM database's `platforms.json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VX in place, instead of shifting VY into VX
+    pub shift: bool,
+    /// FX55 and FX65 increment I by X, instead of X + 1
+    pub memory_increment_by_x: bool,
+    /// FX55 and FX65 leave I unchanged
+    pub memory_leave_i_unchanged: bool,
+    /// BXNN jumps to XNN + VX, instead of NNN + V0
+    pub jump: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF
+    pub logic: bool,
+    /// DXYN waits for the display interrupt, so at most one sprite is drawn per frame
+    pub vblank: bool,
+    /// Sprites wrap around the edges of the screen, instead of being clipped
+    pub wrap: bool,
+    /// In hires, VF counts the sprite rows that collided or were clipped
+    /// at the bottom, like SUPER-CHIP 1.1, instead of being set to 1
+    pub row_collisions: bool,
+    /// FX0A goes on as soon as a key is pressed, like SUPER-CHIP, instead of
+    /// once it is released, like the COSMAC VIP; the key then reads as up
+    /// until it is released
+    pub key_wait_press: bool,
+    /// DXY0 draws a 16x16 sprite in lores too, like SUPER-CHIP 1.1 and
+    /// XO-CHIP, instead of nothing; in hires it always does
+    pub big_sprites: bool,
+}
+
+impl Default for Quirks {
+    /// The interpreter's behaviour for ROMs without a known platform
+    fn default() -> Self {
+        Self {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            jump: true,
+            logic: true,
+            vblank: false,
+            wrap: false,
+            row_collisions: false,
+            key_wait_press: false,
+            big_sprites: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// Sets a quirk by its id in the ROM database; unknown ids are ignored
+    pub fn set(&mut self, id: &str, value: bool) {
+        match id {
+            "shift" => self.shift = value,
+            "memoryIncrementByX" => self.memory_increment_by_x = value,
+            "memoryLeaveIUnchanged" => self.memory_leave_i_unchanged = value,
+            "jump" => self.jump = value,
+            "logic" => self.logic = value,
+            "vblank" => self.vblank = value,
+            "wrap" => self.wrap = value,
+            _ => (),
+        }
+    }
+
+    /// Builds the quirks of a platform from its id and quirk id/value pairs
+    pub fn for_platform<'a>(
+        platform: &str,
+        quirks: impl IntoIterator<Item = (&'a String, &'a bool)>,
+    ) -> Self {
+        // quirks a platform doesn't mention are off
+        let mut result = Self {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            jump: false,
+            logic: false,
+            vblank: false,
+            wrap: false,
+            // not in the database, but it's how SUPER-CHIP draws in hires
+            row_collisions: matches!(platform, "superchip" | "superchip1"),
+            // nor is this; the HP-48 interpreters look for key presses only
+            key_wait_press: matches!(
+                platform,
+                "chip48" | "superchip1" | "superchip" | "megachip8"
+            ),
+            // nor is this; CHIP-8 draws nothing for a sprite of no rows
+            big_sprites: matches!(
+                platform,
+                "superchip1" | "superchip" | "megachip8" | "xochip"
+            ),
+        };
+        for (id, &value) in quirks {
+            result.set(id, value);
+        }
+        result
+    }
+}
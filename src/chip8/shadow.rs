@@ -0,0 +1,89 @@
+use super::cpu::Cpu;
+
+/// Where a shadow run first went its own way
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Frames run before the one that diverged
+    pub frame: u32,
+    /// Instructions into that frame
+    pub instruction: u32,
+    /// The instruction both machines ran last, at its address in the main machine
+    pub pc: u16,
+    pub opcode: u16,
+    /// The state that differs, main machine first
+    pub detail: String,
+}
+
+/// Runs a second machine in lockstep with the main one, with other quirks or
+/// another profile but the same keys and random numbers, and tells where
+/// the two first behave differently
+pub struct Shadow {
+    pub cpu: Cpu,
+    frame: u32,
+    pub divergence: Option<Divergence>,
+    /// Both machines as the frame being run started, main machine first, to
+    /// run it again if they diverge; copied into every frame rather than
+    /// cloned afresh
+    before: Box<(Cpu, Cpu)>,
+}
+
+impl Shadow {
+    /// Starts a shadow run; both machines should be freshly reset with the
+    /// same ROM, and get the same seed
+    pub fn new(main: &mut Cpu, mut cpu: Cpu, seed: u64) -> Self {
+        main.seed_rng(seed);
+        cpu.seed_rng(seed);
+        Self {
+            before: Box::new((main.clone(), cpu.clone())),
+            cpu,
+            frame: 0,
+            divergence: None,
+        }
+    }
+
+    /// Runs a frame on both machines. Once they have diverged, only the
+    /// main machine runs on.
+    pub fn run_frame(&mut self, main: &mut Cpu, ticks: u16) {
+        if self.divergence.is_some() {
+            main.ticks(ticks);
+            return;
+        }
+
+        self.before.0.clone_from(main);
+        self.before.1.clone_from(&self.cpu);
+        self.cpu.keys_down = main.keys_down;
+        main.ticks(ticks);
+        self.cpu.ticks(ticks);
+
+        if main.difference(&self.cpu).is_some() {
+            let (main_before, shadow_before) = &*self.before;
+            self.divergence = Some(self.find_divergence(main_before, shadow_before, ticks));
+        }
+        self.frame += 1;
+    }
+
+    /// Reruns the frame from its start, one more instruction each time,
+    /// to find the instruction after which the machines differ
+    fn find_divergence(&self, main: &Cpu, shadow: &Cpu, ticks: u16) -> Divergence {
+        // the instruction the last rerun stopped before
+        let mut last = (main.pc, main.get_op());
+        let mut limit = 0;
+        loop {
+            let (mut a, mut b) = (main.clone(), shadow.clone());
+            b.keys_down = a.keys_down;
+            let count = a.partial_frame(ticks, limit);
+            b.partial_frame(ticks, limit);
+            if let Some(detail) = a.difference(&b) {
+                return Divergence {
+                    frame: self.frame,
+                    instruction: count,
+                    pc: last.0,
+                    opcode: last.1,
+                    detail,
+                };
+            }
+            last = (a.pc, a.get_op());
+            limit += 1;
+        }
+    }
+}
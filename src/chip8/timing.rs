@@ -0,0 +1,65 @@
+use crate::{N, X};
+
+/// Machine cycles in one 60Hz frame of the COSMAC VIP (1.76MHz, 8 clocks per cycle)
+pub const VIP_CYCLES_PER_FRAME: i32 = 3668;
+
+/// Machine cycles per frame taken by the CDP1861's display DMA (128 lines of 8 bytes)
+/// and the interrupt routine, which the interpreter can't use
+pub const VIP_DISPLAY_CYCLES: i32 = 1024 + 29;
+
+/// Machine cycles the interpreter spends fetching and decoding every instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How instructions are paced within a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes one tick, at the clock's instruction rate
+    Instructions,
+    /// Every instruction costs its COSMAC VIP machine cycles, within a frame of
+    /// `VIP_CYCLES_PER_FRAME` cycles
+    VipCycles,
+}
+
+/// Approximate machine cycles the COSMAC VIP interpreter needs for an instruction,
+/// after Laurence Scotford's analysis of the original interpreter.
+///
+/// `vx` is the value of VX before the instruction runs, which decides how many
+/// bytes of every sprite row must be shifted and drawn. `skipped` tells whether
+/// a conditional skip was taken.
+pub fn vip_cycles(opcode: u16, vx: u8, skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+    let execute = match opcode & 0xf000 {
+        0x0000 => match opcode & 0xfff {
+            0x0e0 => 3078,
+            0x0ee => 10,
+            // machine code routines take however long they take
+            _ => 0,
+        },
+        0x1000 => 12,
+        0x2000 => 26,
+        0x3000 | 0x4000 => 10 + skip,
+        0x5000 | 0x9000 => 18 + skip,
+        0x6000 => 6,
+        0x7000 => 10,
+        0x8000 => 44,
+        0xa000 => 12,
+        0xb000 => 22,
+        0xc000 => 36,
+        0xd000 => {
+            // unaligned sprites straddle two bytes of display memory
+            let rows = N!(opcode) as u32;
+            let per_row = if vx % 8 == 0 { 34 } else { 54 };
+            26 + rows * per_row
+        }
+        0xe000 => 14 + skip,
+        _ => match opcode & 0xff {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0a => 19,
+            0x1e | 0x29 | 0x30 => 16,
+            0x33 => 84 + 16 * (vx as u32 / 100 + vx as u32 / 10 % 10 + vx as u32 % 10),
+            0x55 | 0x65 => 14 + 14 * (X!(opcode) as u32 + 1),
+            _ => 0,
+        },
+    };
+    VIP_FETCH_CYCLES + execute
+}
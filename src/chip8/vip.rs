@@ -0,0 +1,226 @@
+use super::cdp1802::{Cdp1802, Cdp1802Bus};
+use super::font;
+use super::gpu::{Gpu, LORES};
+use super::machine::Machine;
+use super::timing::VIP_CYCLES_PER_FRAME;
+
+/// RAM of a 4Kb COSMAC VIP; addresses below 0x8000 wrap around it
+const RAM_SIZE: usize = 0x1000;
+
+/// The monitor ROM, at 0x8000 and mirrored up to 0xFFFF
+const ROM_SIZE: usize = 0x200;
+
+/// Where the interpreter expects the table with the low bytes of the font's addresses
+const FONT_TABLE: usize = 0x100;
+
+/// Where our font glyphs go in the ROM page, after the interrupt routine
+const FONT_GLYPHS: usize = 0x180;
+
+/// Where the interpreter points R1: the entry of the display interrupt routine
+const INTERRUPT_ENTRY: usize = 0x146;
+
+/// The display interrupt routine, taking the place of the one in the VIP's
+/// monitor ROM. It starts at `INTERRUPT_ENTRY - 2`, so its exit falls through
+/// to the entry for the next interrupt. Its cycles are counted to match the
+/// CDP1861's DMA, which shows each row of the display page on 4 lines.
+#[rustfmt::skip]
+const INTERRUPT_ROUTINE: [u8; 45] = [
+    0x72,             // 44: LDXA        restore D
+    0x70,             // 45: RET         restore X and P, enable interrupts
+    0x22, 0x78,       // 46: DEC 2; SAV  save T
+    0x22, 0x52,       // 48: DEC 2; STR 2  save D
+    0x19,             // 4A: INC 9       random seed
+    0x9B, 0xB0,       // 4B: GHI B; PHI 0  R0 to the display page
+    0xF8, 0x00, 0xA0, // 4D: LDI 00; PLO 0
+    0xE2, 0xE2,       // 50: SEX 2; SEX 2  wait for the first line
+    0x80, 0xE2, 0xE2, // 52: GLO 0; SEX 2; SEX 2  first line of a row
+    0x20, 0xA0, 0xE2, // 55: DEC 0; PLO 0; SEX 2  and the same row again
+    0x20, 0xA0, 0xE2, // 58: DEC 0; PLO 0; SEX 2
+    0x20, 0xA0,       // 5B: DEC 0; PLO 0
+    0x3C, 0x52,       // 5D: BN1 52      until the end of the display
+    0x98, 0x32, 0x65, // 5F: GHI 8; BZ 65  count down the delay timer
+    0xFF, 0x01, 0xB8, // 62: SMI 01; PHI 8
+    0x88, 0x32, 0x6E, // 65: GLO 8; BZ 6E  count down the tone timer
+    0xFF, 0x01, 0xA8, // 68: SMI 01; PLO 8
+    0x7B, 0x30, 0x6F, // 6B: SEQ; BR 6F   the tone sounds while it runs
+    0x7A,             // 6E: REQ
+    0x30, 0x44,       // 6F: BR 44
+];
+
+/// Machine cycles from the display interrupt to the first line of DMA
+const INTERRUPT_TO_DMA: u32 = 29;
+
+/// Machine cycles per display line: 8 of DMA, 6 for the CPU
+const LINE_CYCLES: u32 = 14;
+
+/// Display lines with DMA; every row of the display page is shown 4 times
+const DMA_LINES: usize = 128;
+
+/// Memory, keypad and video of the COSMAC VIP, as seen by its CDP1802
+struct VipBus {
+    ram: Vec<u8>,
+    rom: [u8; ROM_SIZE],
+    keys_down: [bool; 16],
+    /// The key selected with OUT 2, reported on EF3
+    key_latch: u8,
+    /// The CDP1861 is switched on with INP 1 and off with OUT 1
+    display_on: bool,
+    /// Display lines output so far this frame
+    line: usize,
+    /// EF1, set by the CDP1861 during the last 4 display lines
+    end_of_display: bool,
+}
+
+impl Cdp1802Bus for VipBus {
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.rom[address as usize % ROM_SIZE]
+        } else {
+            self.ram[address as usize % RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if address & 0x8000 == 0 {
+            self.ram[address as usize % RAM_SIZE] = data;
+        }
+    }
+
+    fn output(&mut self, port: u8, data: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = data & 0xf,
+            _ => (),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0
+    }
+
+    fn flag(&self, line: u8) -> bool {
+        match line {
+            1 => self.end_of_display,
+            3 => self.keys_down[self.key_latch as usize],
+            _ => false,
+        }
+    }
+}
+
+/// A COSMAC VIP running the original CHIP-8 interpreter: an RCA CDP1802,
+/// a CDP1861 video chip reading the display page by DMA, the hex keypad
+/// and the tone generator driven by Q
+pub struct Vip {
+    cpu: Cdp1802,
+    bus: VipBus,
+    gpu: Gpu,
+    /// Machine cycles the last frame ran past its end, which this one has
+    /// already used
+    overshoot: u32,
+}
+
+impl Vip {
+    /// Loads the interpreter image at 0x000 and the program at 0x200.
+    /// Only the monitor's display interrupt routine and font are provided,
+    /// so the interpreter must be a plain CHIP-8 interpreter image.
+    pub fn new(interpreter: &[u8], program: &[u8]) -> Self {
+        let mut ram = vec![0; RAM_SIZE];
+        let interpreter = &interpreter[..interpreter.len().min(0x200)];
+        ram[..interpreter.len()].copy_from_slice(interpreter);
+        let program = &program[..program.len().min(RAM_SIZE - 0x200)];
+        ram[0x200..0x200 + program.len()].copy_from_slice(program);
+
+        let mut rom = [0; ROM_SIZE];
+        let routine = INTERRUPT_ENTRY - 2;
+        rom[routine..routine + INTERRUPT_ROUTINE.len()].copy_from_slice(&INTERRUPT_ROUTINE);
+        rom[FONT_GLYPHS..FONT_GLYPHS + font::VIP.glyphs.len()].copy_from_slice(font::VIP.glyphs);
+        for digit in 0..16 {
+            rom[FONT_TABLE + digit] = (FONT_GLYPHS + digit * font::VIP.height as usize) as u8;
+        }
+
+        // the monitor leaves the top page of RAM in R1.1
+        let mut cpu = Cdp1802::new();
+        cpu.r[1] = (RAM_SIZE as u16 - 0x100) & 0xff00;
+
+        Self {
+            cpu,
+            bus: VipBus {
+                ram,
+                rom,
+                keys_down: [false; 16],
+                key_latch: 0,
+                display_on: false,
+                line: 0,
+                end_of_display: false,
+            },
+            gpu: Gpu::new(),
+            overshoot: 0,
+        }
+    }
+
+    /// Outputs one display line of DMA, onto its row of the screen
+    fn dma_line(&mut self) {
+        let mut bytes = [0; 8];
+        for byte in bytes.iter_mut() {
+            *byte = self.cpu.dma_out(&mut self.bus);
+        }
+        let row = self.bus.line * LORES.1 / DMA_LINES;
+        self.bus.end_of_display = self.bus.line >= DMA_LINES - 4;
+        let pixels = (u64::from_be_bytes(bytes) as u128) << 64;
+        if self.gpu.buffer[row] != pixels {
+            self.gpu.buffer[row] = pixels;
+            self.gpu.has_changed = true;
+        }
+        self.bus.line += 1;
+    }
+}
+
+impl Machine for Vip {
+    fn run_frame(&mut self, _ticks: u16) {
+        let frame = VIP_CYCLES_PER_FRAME as u32;
+        // the display keeps its own time, so only the CPU makes up for the
+        // last instruction of the last frame
+        let mut cycles = self.overshoot;
+        let mut next_line = u32::MAX;
+        self.bus.line = DMA_LINES;
+
+        if self.bus.display_on {
+            // the interrupt takes a cycle to serve
+            self.cpu.interrupt();
+            cycles += 1;
+            next_line = INTERRUPT_TO_DMA;
+            self.bus.line = 0;
+        }
+
+        while cycles < frame {
+            if cycles >= next_line {
+                if self.bus.line < DMA_LINES {
+                    self.dma_line();
+                    cycles += 8;
+                    next_line += LINE_CYCLES;
+                    continue;
+                }
+                // the last display line is over
+                self.bus.end_of_display = false;
+                next_line = u32::MAX;
+            }
+            cycles += self.cpu.step(&mut self.bus);
+        }
+        self.overshoot = cycles - frame;
+    }
+
+    fn set_keys(&mut self, keys_down: [bool; 16]) {
+        self.bus.keys_down = keys_down;
+    }
+
+    fn is_beeping(&self) -> bool {
+        self.cpu.q
+    }
+
+    fn gpu(&mut self) -> &mut Gpu {
+        &mut self.gpu
+    }
+}
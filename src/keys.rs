@@ -1,7 +1,104 @@
+use crate::chip8::profile::Keypad;
 use egui::Key;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// User keymaps, saved between sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeymapSettings {
+    pub default: [Key; 16],
+    /// The default on machines with the DREAM 6800 keypad, once saved
+    #[serde(default)]
+    pub dream_6800_default: Option<[Key; 16]>,
+    /// Keymaps for individual ROMs, by SHA-1 hash
+    pub per_rom: HashMap<String, [Key; 16]>,
+}
+
+impl Default for KeymapSettings {
+    fn default() -> Self {
+        Self {
+            default: KeyMapper::COSMAC_ELF,
+            dream_6800_default: None,
+            per_rom: HashMap::new(),
+        }
+    }
+}
+
+impl KeymapSettings {
+    /// The keymap to use for a ROM, falling back to the user's default for
+    /// the machine's keypad
+    pub fn key_map_for(&self, hash: Option<&str>, keypad: Keypad) -> [Key; 16] {
+        hash.and_then(|hash| self.per_rom.get(hash))
+            .copied()
+            .unwrap_or(self.default_for(keypad))
+    }
+
+    /// The user's default for a keypad; on the DREAM 6800's, the DREAM 6800
+    /// keymap until one is saved
+    pub fn default_for(&self, keypad: Keypad) -> [Key; 16] {
+        match keypad {
+            Keypad::Vip => self.default,
+            Keypad::Dream6800 => self.dream_6800_default.unwrap_or(KeyMapper::DREAM_6800),
+        }
+    }
+
+    pub fn set_default(&mut self, keypad: Keypad, key_map: [Key; 16]) {
+        match keypad {
+            Keypad::Vip => self.default = key_map,
+            Keypad::Dream6800 => self.dream_6800_default = Some(key_map),
+        }
+    }
+}
 
 pub struct KeyMapper {
     pub key_map: [Key; 16],
+    pub controls: Vec<(Control, u8)>,
+}
+
+/// Semantic game controls, as used by the `keys` field in the ROM database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Player2Up,
+    Player2Down,
+}
+
+impl Control {
+    /// Physical keys that drive this control: the arrow keys, WASD,
+    /// and gamepad-style J/K buttons
+    pub fn keys(self, two_players: bool) -> &'static [Key] {
+        match (self, two_players) {
+            // player 1 moves to WASD when player 2 takes the arrow keys
+            (Control::Up, true) => &[Key::W],
+            (Control::Down, true) => &[Key::S],
+            (Control::Up, false) => &[Key::ArrowUp, Key::W],
+            (Control::Down, false) => &[Key::ArrowDown, Key::S],
+            (Control::Left, _) => &[Key::ArrowLeft, Key::A],
+            (Control::Right, _) => &[Key::ArrowRight, Key::D],
+            (Control::A, _) => &[Key::J, Key::Z, Key::Enter],
+            (Control::B, _) => &[Key::K, Key::X],
+            (Control::Player2Up, _) => &[Key::ArrowUp],
+            (Control::Player2Down, _) => &[Key::ArrowDown],
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Control::Up => "Up",
+            Control::Down => "Down",
+            Control::Left => "Left",
+            Control::Right => "Right",
+            Control::A => "A",
+            Control::B => "B",
+            Control::Player2Up => "P2 Up",
+            Control::Player2Down => "P2 Down",
+        }
+    }
 }
 
 impl KeyMapper {
@@ -25,31 +122,110 @@ impl KeyMapper {
         Key::V,
     ];
 
-    // Constant key map for DREAM 6800
-    #[allow(dead_code)]
-    pub const DREAM_6800: [Key; 16] = [
+    // Constant key map for COSMAC ELF on an AZERTY keyboard
+    pub const COSMAC_ELF_AZERTY: [Key; 16] = [
+        Key::X,
         Key::Num1,
         Key::Num2,
         Key::Num3,
-        Key::Num4,
-        Key::Q,
-        Key::W,
-        Key::E,
-        Key::R,
         Key::A,
+        Key::Z,
+        Key::E,
+        Key::Q,
         Key::S,
         Key::D,
+        Key::W,
+        Key::C,
+        Key::Num4,
+        Key::R,
         Key::F,
+        Key::V,
+    ];
+
+    // Constant key map for DREAM 6800, laid out like its keypad: C D E F on
+    // top, 0 1 2 3 at the bottom
+    pub const DREAM_6800: [Key; 16] = [
         Key::Z,
         Key::X,
         Key::C,
         Key::V,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::F,
+        Key::Q,
+        Key::W,
+        Key::E,
+        Key::R,
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+    ];
+
+    // Key maps that can be picked in the keymap editor
+    pub const PRESETS: [(&'static str, [Key; 16]); 3] = [
+        ("COSMAC VIP (QWERTY)", Self::COSMAC_ELF),
+        ("COSMAC VIP (AZERTY)", Self::COSMAC_ELF_AZERTY),
+        ("DREAM 6800", Self::DREAM_6800),
     ];
 
     // Create a new KeyMapper with a custom or default key map
     pub fn new(key_map: Option<[Key; 16]>) -> Self {
         Self {
             key_map: key_map.unwrap_or(Self::COSMAC_ELF),
+            controls: Vec::new(),
+        }
+    }
+
+    fn two_players(&self) -> bool {
+        self.controls
+            .iter()
+            .any(|(control, _)| matches!(control, Control::Player2Up | Control::Player2Down))
+    }
+
+    /// Returns which CHIP-8 keys are held down. A key that is bound to a
+    /// control drives the control's CHIP-8 key, rather than the one it has
+    /// in the hex keypad layout.
+    pub fn keys_down(&self, input: &egui::InputState) -> [bool; 16] {
+        let two_players = self.two_players();
+        let mut keys_down = [false; 16];
+        for (i, key) in self.key_map.iter().enumerate() {
+            let taken = self.controls.iter().any(|&(control, chip8_key)| {
+                chip8_key as usize & 0xf != i && control.keys(two_players).contains(key)
+            });
+            keys_down[i] = !taken && input.key_down(*key);
+        }
+        for (control, chip8_key) in &self.controls {
+            if control
+                .keys(two_players)
+                .iter()
+                .any(|key| input.key_down(*key))
+            {
+                keys_down[*chip8_key as usize & 0xf] = true;
+            }
+        }
+        keys_down
+    }
+
+    /// Describes the active controls, e.g. "Up (Up/W): 2, A (J/Z/Enter): 5"
+    pub fn describe_controls(&self) -> Option<String> {
+        if self.controls.is_empty() {
+            return None;
         }
+        let two_players = self.two_players();
+        let descriptions: Vec<String> = self
+            .controls
+            .iter()
+            .map(|(control, chip8_key)| {
+                let keys: Vec<&str> = control
+                    .keys(two_players)
+                    .iter()
+                    .map(|key| key.name())
+                    .collect();
+                format!("{} ({}): {:X}", control.name(), keys.join("/"), chip8_key)
+            })
+            .collect();
+        Some(descriptions.join(", "))
     }
 }
@@ -3,6 +3,14 @@
 mod app;
 pub use app::TemplateApp;
 
-mod chip8;
+pub mod chip8;
 mod keys;
+pub mod rom_analyzer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rom_scanner;
 mod roms_db;
+#[cfg(not(target_arch = "wasm32"))]
+mod symbols;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod touch;
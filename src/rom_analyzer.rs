@@ -0,0 +1,215 @@
+use crate::chip8::quirks::Quirks;
+use crate::roms_db;
+use crate::{NNN, X, Y};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Below this many reachable instructions, there's too little code to go on
+const FEW_INSTRUCTIONS: usize = 16;
+
+/// A guess at the platform a ROM that isn't in the database was written for
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// Platform id, as in the ROM database's `platforms.json`
+    pub platform: &'static str,
+    pub quirks: Quirks,
+    /// How sure the guess is, from 0 to 1
+    pub confidence: f32,
+    /// What the guess is based on, one finding per line
+    pub evidence: Vec<String>,
+}
+
+/// Counts of the telling instructions in the reachable code
+#[derive(Debug, Default)]
+struct Stats {
+    instructions: usize,
+    xo_chip: usize,
+    super_chip: usize,
+    machine_code: usize,
+    /// 8XY6 and 8XYE
+    shifts: usize,
+    /// Shifts that read a VY other than VX or V0, as only makes sense without the shift quirk
+    shifts_from_vy: usize,
+    /// BXNN with a nonzero X right after VX is set, as only makes sense with the jump quirk
+    jumps_by_vx: usize,
+    /// BNNN with a nonzero X right after V0 is set, as only makes sense without it
+    jumps_by_v0: usize,
+}
+
+/// Guesses the platform and quirks of a ROM, from the instructions that can be
+/// reached from `start` by following jumps, calls and skips
+pub fn analyze(rom: &[u8], start: u16) -> Analysis {
+    let stats = count(rom, start, &trace(rom, start));
+    let mut evidence = Vec::new();
+
+    let (platform, mut confidence) = if stats.xo_chip > 0 {
+        evidence.push(format!("{} XO-CHIP instructions", stats.xo_chip));
+        ("xochip", certainty(stats.xo_chip))
+    } else if stats.super_chip > 0 {
+        evidence.push(format!("{} SUPER-CHIP instructions", stats.super_chip));
+        ("superchip", certainty(stats.super_chip))
+    } else if stats.machine_code > 0 {
+        evidence.push(format!("{} calls to machine code", stats.machine_code));
+        ("hybridVIP", certainty(stats.machine_code))
+    } else {
+        evidence.push("Only CHIP-8 instructions".to_string());
+        ("originalChip8", 0.4)
+    };
+
+    let mut quirks = roms_db::get_platform(platform)
+        .map(|p| Quirks::for_platform(platform, &p.quirks))
+        .unwrap_or_default();
+
+    if stats.shifts_from_vy > 0 {
+        // the program expects VY to be shifted into VX
+        evidence.push(format!(
+            "{} shifts from another register",
+            stats.shifts_from_vy
+        ));
+        expect(&mut quirks.shift, false, &mut confidence);
+    } else if stats.shifts > 0 {
+        evidence.push(format!("{} shifts in place", stats.shifts));
+    }
+    match stats.jumps_by_vx.cmp(&stats.jumps_by_v0) {
+        Ordering::Greater => {
+            evidence.push(format!("{} jumps offset by VX", stats.jumps_by_vx));
+            expect(&mut quirks.jump, true, &mut confidence);
+        }
+        Ordering::Less => {
+            evidence.push(format!("{} jumps offset by V0", stats.jumps_by_v0));
+            expect(&mut quirks.jump, false, &mut confidence);
+        }
+        // no jumps, or as many of each
+        Ordering::Equal => (),
+    }
+    if stats.instructions < FEW_INSTRUCTIONS {
+        evidence.push(format!("Only {} instructions found", stats.instructions));
+        confidence *= 0.5;
+    }
+
+    Analysis {
+        platform,
+        quirks,
+        confidence,
+        evidence,
+    }
+}
+
+/// Sets a quirk to what the code expects: the guess is less sure when the
+/// platform disagrees, and surer when it agrees
+fn expect(quirk: &mut bool, expected: bool, confidence: &mut f32) {
+    if *quirk == expected {
+        *confidence += (1.0 - *confidence) * 0.3;
+    } else {
+        *quirk = expected;
+        *confidence *= 0.8;
+    }
+}
+
+/// Confidence from the number of platform specific instructions seen
+fn certainty(count: usize) -> f32 {
+    (0.6 + 0.1 * count as f32).min(0.95)
+}
+
+fn opcode_at(rom: &[u8], start: u16, address: u16) -> Option<u16> {
+    let offset = address.checked_sub(start)? as usize;
+    let high = *rom.get(offset)?;
+    let low = *rom.get(offset + 1)?;
+    Some(u16::from_be_bytes([high, low]))
+}
+
+/// Finds the addresses of all instructions reachable from `start`
+fn trace(rom: &[u8], start: u16) -> Vec<u16> {
+    let mut reached = HashSet::new();
+    let mut todo = vec![start];
+
+    while let Some(address) = todo.pop() {
+        let Some(opcode) = opcode_at(rom, start, address) else {
+            continue;
+        };
+        if !reached.insert(address) {
+            continue;
+        }
+
+        // XO-CHIP's F000 NNNN is 4 bytes long, which skips must jump over
+        let length = |address: u16| match opcode_at(rom, start, address) {
+            Some(0xf000) => 4,
+            _ => 2,
+        };
+        let next = address.wrapping_add(length(address));
+        match opcode & 0xf000 {
+            // return, exit, and jumps to computed addresses end the path
+            0x0000 if opcode == 0x00ee || opcode == 0x00fd => (),
+            0xb000 => (),
+            0x1000 => todo.push(NNN!(opcode)),
+            0x2000 => todo.extend([NNN!(opcode), next]),
+            0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xe000 => {
+                todo.extend([next, next.wrapping_add(length(next))]);
+            }
+            _ => todo.push(next),
+        }
+    }
+
+    let mut addresses: Vec<u16> = reached.into_iter().collect();
+    addresses.sort_unstable();
+    addresses
+}
+
+fn count(rom: &[u8], start: u16, addresses: &[u16]) -> Stats {
+    let mut stats = Stats::default();
+    for &address in addresses {
+        let Some(opcode) = opcode_at(rom, start, address) else {
+            continue;
+        };
+        stats.instructions += 1;
+        let (x, y) = (X!(opcode), Y!(opcode));
+
+        match opcode & 0xf000 {
+            0x0000 => match opcode {
+                0x0000 | 0x00e0 | 0x00ee => (),
+                0x00d0..=0x00df => stats.xo_chip += 1,
+                0x00c0..=0x00cf | 0x00fb..=0x00ff => stats.super_chip += 1,
+                _ => stats.machine_code += 1,
+            },
+            0x5000 if opcode & 0xf == 2 || opcode & 0xf == 3 => stats.xo_chip += 1,
+            0x8000 if opcode & 0xf == 0x6 || opcode & 0xf == 0xe => {
+                stats.shifts += 1;
+                if x != y && y != 0 {
+                    stats.shifts_from_vy += 1;
+                }
+            }
+            0xb000 if x != 0 => {
+                // the register the instruction before sets is the offset
+                let before = address.wrapping_sub(2);
+                let set = addresses
+                    .binary_search(&before)
+                    .ok()
+                    .and_then(|_| opcode_at(rom, start, before))
+                    .and_then(sets);
+                if set == Some(x) {
+                    stats.jumps_by_vx += 1;
+                } else if set == Some(0) {
+                    stats.jumps_by_v0 += 1;
+                }
+            }
+            0xd000 if opcode & 0xf == 0 => stats.super_chip += 1,
+            0xf000 => match opcode & 0xff {
+                0x00 if opcode == 0xf000 => stats.xo_chip += 1,
+                0x01 | 0x02 | 0x3a => stats.xo_chip += 1,
+                0x30 | 0x75 | 0x85 => stats.super_chip += 1,
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+    stats
+}
+
+/// The register an instruction sets on its own, if any
+fn sets(opcode: u16) -> Option<usize> {
+    match opcode & 0xf000 {
+        0x6000 | 0x7000 | 0x8000 | 0xc000 => Some(X!(opcode)),
+        0xf000 if opcode & 0xff == 0x07 || opcode & 0xff == 0x0a => Some(X!(opcode)),
+        _ => None,
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{chip8, roms_db};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the cache file that is written into every scanned directory
+pub const INDEX_FILE_NAME: &str = ".chippie-index.json";
+
+/// Files larger than this can't be CHIP-8 programs: the most any of the
+/// machines has room for (XO-CHIP, 64Kb less the interpreter area)
+fn max_rom_size() -> u64 {
+    chip8::profile::PROFILES
+        .iter()
+        .map(|profile| (profile.memory_size - profile.program_start as usize) as u64)
+        .max()
+        .unwrap_or(0)
+}
+
+/// A single file found while scanning a ROM directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Path relative to the scanned directory, with `/` separators
+    pub path: String,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch, used to detect changes
+    pub modified: u64,
+    pub sha1: String,
+    /// Index into `roms_db::PROGRAMS`, if the hash is known
+    pub program: Option<u32>,
+}
+
+impl IndexEntry {
+    pub fn get_title(&self) -> Option<&'static str> {
+        self.program
+            .and_then(|id| roms_db::PROGRAMS.get(id as usize))
+            .map(|program| program.get_title())
+    }
+}
+
+/// Cached index of a user ROM directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RomIndex {
+    pub root: PathBuf,
+    pub entries: Vec<IndexEntry>,
+}
+
+/// A known program, and all files in the collection that contain one of its ROMs
+#[derive(Debug, Serialize)]
+pub struct MatchedTitle<'a> {
+    pub title: &'static str,
+    pub files: Vec<&'a IndexEntry>,
+}
+
+/// Files that share the same SHA-1 hash
+#[derive(Debug, Serialize)]
+pub struct Duplicate<'a> {
+    pub sha1: &'a str,
+    pub files: Vec<&'a str>,
+}
+
+/// What the collection covers, as exported to JSON
+#[derive(Debug, Serialize)]
+pub struct IndexReport<'a> {
+    pub root: &'a Path,
+    pub files: usize,
+    pub matched: Vec<MatchedTitle<'a>>,
+    pub unknown: Vec<&'a IndexEntry>,
+    pub duplicates: Vec<Duplicate<'a>>,
+}
+
+impl RomIndex {
+    /// Opens the index of `root`, reusing the cache file when there is one,
+    /// and brings it up to date with the files on disk
+    pub fn open(root: &Path) -> io::Result<Self> {
+        let mut index = Self::load_cache(root).unwrap_or_else(|| RomIndex {
+            root: root.to_path_buf(),
+            entries: Vec::new(),
+        });
+        index.update()?;
+        Ok(index)
+    }
+
+    /// Rescans the directory and caches the result. The cache only saves
+    /// hashing next time, so a folder that can't be written to, like one on
+    /// a read-only medium, is scanned all the same.
+    pub fn update(&mut self) -> io::Result<()> {
+        self.refresh()?;
+        if let Err(e) = self.save_cache() {
+            log::warn!("Can't save the index of {}: {}", self.root.display(), e);
+        }
+        Ok(())
+    }
+
+    fn load_cache(root: &Path) -> Option<Self> {
+        let json_data = fs::read_to_string(root.join(INDEX_FILE_NAME)).ok()?;
+        let mut index: RomIndex = serde_json::from_str(&json_data).ok()?;
+        // the directory may have been moved since the cache was written
+        index.root = root.to_path_buf();
+        Some(index)
+    }
+
+    pub fn save_cache(&self) -> io::Result<()> {
+        let json_data = serde_json::to_string(self)?;
+        fs::write(self.root.join(INDEX_FILE_NAME), json_data)
+    }
+
+    /// Rescans the directory. Only files that are new, or whose size or
+    /// modification time changed, are hashed again. The index is left as it
+    /// was if the directory can't be read.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let mut files = Vec::new();
+        collect_files(&self.root, &mut files, &mut HashSet::new())?;
+
+        let mut cached: HashMap<&str, &IndexEntry> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let max_rom_size = max_rom_size();
+        let mut entries = Vec::new();
+
+        for file in files {
+            let metadata = match fs::metadata(&file) {
+                Ok(metadata) if metadata.len() <= max_rom_size => metadata,
+                _ => continue,
+            };
+            let Some(path) = relative_path(&self.root, &file) else {
+                continue;
+            };
+            let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs());
+
+            // keep the cached hash if the file looks unchanged
+            if let Some(entry) = cached.remove(path.as_str()) {
+                if entry.size == size && entry.modified == modified {
+                    entries.push(entry.clone());
+                    continue;
+                }
+            }
+
+            let Ok(bindata) = fs::read(&file) else {
+                continue;
+            };
+            let sha1 = roms_db::calculate_sha1(&bindata);
+            let program = roms_db::HASHES.get(&sha1).copied();
+            entries.push(IndexEntry {
+                path,
+                size,
+                modified,
+                sha1,
+                program,
+            });
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        self.entries = entries;
+        Ok(())
+    }
+
+    pub fn full_path(&self, entry: &IndexEntry) -> PathBuf {
+        self.root.join(&entry.path)
+    }
+
+    /// Known programs in the collection, sorted by title
+    pub fn matched(&self) -> Vec<MatchedTitle<'_>> {
+        let mut titles: BTreeMap<&'static str, Vec<&IndexEntry>> = BTreeMap::new();
+        for entry in &self.entries {
+            if let Some(title) = entry.get_title() {
+                titles.entry(title).or_default().push(entry);
+            }
+        }
+        titles
+            .into_iter()
+            .map(|(title, files)| MatchedTitle { title, files })
+            .collect()
+    }
+
+    /// Files whose hash isn't in `roms_db::HASHES`
+    pub fn unknown(&self) -> Vec<&IndexEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.program.is_none())
+            .collect()
+    }
+
+    /// Groups of files with identical contents
+    pub fn duplicates(&self) -> Vec<Duplicate<'_>> {
+        let mut by_hash: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for entry in &self.entries {
+            by_hash.entry(&entry.sha1).or_default().push(&entry.path);
+        }
+        by_hash
+            .into_iter()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|(sha1, files)| Duplicate { sha1, files })
+            .collect()
+    }
+
+    pub fn report(&self) -> IndexReport<'_> {
+        IndexReport {
+            root: &self.root,
+            files: self.entries.len(),
+            matched: self.matched(),
+            unknown: self.unknown(),
+            duplicates: self.duplicates(),
+        }
+    }
+
+    pub fn export_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.report())
+    }
+}
+
+/// Collects the files under `dir`. Symlinks are followed, but each directory
+/// is only visited once, so that a link to a parent doesn't loop forever.
+/// Only an error reading `dir` itself is returned; subdirectories that can't
+/// be read are logged and left out.
+fn collect_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> io::Result<()> {
+    if !visited.insert(fs::canonicalize(dir)?) {
+        return Ok(());
+    }
+    for dir_entry in fs::read_dir(dir)? {
+        let path = match dir_entry {
+            Ok(dir_entry) => dir_entry.path(),
+            Err(e) => {
+                log::warn!("Can't read an entry of {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        if path.file_name().and_then(|name| name.to_str()) == Some(INDEX_FILE_NAME) {
+            continue;
+        }
+        if path.is_dir() {
+            if let Err(e) = collect_files(&path, files, visited) {
+                log::warn!("Can't read {}: {}", path.display(), e);
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn relative_path(root: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    Some(parts.join("/"))
+}
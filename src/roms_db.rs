@@ -1,5 +1,8 @@
+use crate::keys::Control;
+use egui::Color32;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use std::collections::HashMap;
 
 // Embed the binary data (e.g., a .ch8 file) into the program
@@ -81,12 +84,26 @@ pub static ROMS2: Lazy<HashMap<&'static str, Vec<u8>>> = Lazy::new(|| {
     programs
 });
 
+pub fn calculate_sha1(data: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
 pub static HASHES: Lazy<HashMap<String, u32>> =
     Lazy::new(|| load_embedded_sha1_hashes().unwrap_or_default());
 
 pub static PROGRAMS: Lazy<Vec<Program>> =
     Lazy::new(|| load_embedded_programs().unwrap_or_default());
 
+pub static PLATFORMS: Lazy<Vec<Platform>> =
+    Lazy::new(|| load_embedded_platforms().unwrap_or_default());
+
+/// Finds a platform by its id, e.g. "originalChip8"
+pub fn get_platform(id: &str) -> Option<&'static Platform> {
+    PLATFORMS.iter().find(|platform| platform.id == id)
+}
+
 // Function to load the embedded JSON and parse it into a HashMap<String, u32>
 fn load_embedded_sha1_hashes() -> Result<HashMap<String, u32>, Box<dyn std::error::Error>> {
     // Use the include_str! macro to embed the JSON file into the binary
@@ -106,6 +123,85 @@ pub struct Colors {
     silence: Option<String>,
 }
 
+impl Colors {
+    /// Parses the hex color strings, or returns None if any of them is malformed.
+    /// XO-CHIP lists two more pixel colors, for its second bit plane; the
+    /// display has one plane, so only the first two are used.
+    pub fn to_palette(&self) -> Option<Palette> {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|hex| Color32::from_hex(hex).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [off, on, ..] = pixels[..] else {
+            return None;
+        };
+        let buzzer = match &self.buzzer {
+            Some(hex) => Some(Color32::from_hex(hex).ok()?),
+            None => None,
+        };
+        let silence = match &self.silence {
+            Some(hex) => Some(Color32::from_hex(hex).ok()?),
+            None => None,
+        };
+        Some(Palette {
+            off,
+            on,
+            buzzer,
+            silence,
+        })
+    }
+}
+
+/// Colors used to draw the emulator screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// Pixels that are off
+    pub off: Color32,
+    /// Pixels that are on
+    pub on: Color32,
+    /// Background around the screen while the sound timer runs
+    pub buzzer: Option<Color32>,
+    /// Background around the screen while it's quiet
+    pub silence: Option<Color32>,
+}
+
+impl Palette {
+    /// The colors of pixels that are off and on, without backgrounds
+    pub fn new(off: Color32, on: Color32) -> Self {
+        Palette {
+            off,
+            on,
+            buzzer: None,
+            silence: None,
+        }
+    }
+
+    pub fn color_off(&self) -> Color32 {
+        self.off
+    }
+
+    pub fn color_on(&self) -> Color32 {
+        self.on
+    }
+
+    /// Background color to show around the screen, depending on the buzzer
+    pub fn background(&self, beeping: bool) -> Option<Color32> {
+        if beeping {
+            self.buzzer.or(self.silence)
+        } else {
+            self.silence
+        }
+    }
+}
+
+impl Default for Palette {
+    /// White on black
+    fn default() -> Self {
+        Palette::new(Color32::BLACK, Color32::WHITE)
+    }
+}
+
 // Struct for optional keys mapping
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Keys {
@@ -121,6 +217,25 @@ pub struct Keys {
     player2_down: Option<u8>,
 }
 
+impl Keys {
+    /// The CHIP-8 key for each control that the ROM uses
+    pub fn controls(&self) -> Vec<(Control, u8)> {
+        [
+            (Control::Up, self.up),
+            (Control::Down, self.down),
+            (Control::Left, self.left),
+            (Control::Right, self.right),
+            (Control::A, self.a),
+            (Control::B, self.b),
+            (Control::Player2Up, self.player2_up),
+            (Control::Player2Down, self.player2_down),
+        ]
+        .into_iter()
+        .filter_map(|(control, key)| key.map(|key| (control, key)))
+        .collect()
+    }
+}
+
 // // Struct for quirky platform behavior (optional)
 // #[derive(Debug, Serialize, Deserialize)]
 // pub struct QuirkyPlatform {
@@ -164,17 +279,43 @@ impl Rom {
         }
     }
 
+    pub fn get_platform_ids(&self) -> &[String] {
+        &self.platforms
+    }
+
     pub fn get_tickrate(&self) -> Option<u16> {
         self.tickrate
     }
 
     pub fn get_colors(&self) -> Option<String> {
-        self.colors.as_ref().map(|_| "Colors exist".to_string())
+        self.colors.as_ref().map(|colors| {
+            let mut text = colors.pixels.join(", ");
+            if let Some(buzzer) = &colors.buzzer {
+                text += &format!(", buzzer {}", buzzer);
+            }
+            if let Some(silence) = &colors.silence {
+                text += &format!(", silence {}", silence);
+            }
+            text
+        })
+    }
+
+    pub fn get_palette(&self) -> Option<Palette> {
+        self.colors.as_ref().and_then(Colors::to_palette)
     }
 
     pub fn get_keys(&self) -> Option<String> {
-        // Replace with actual handling if Keys needs to return a string
-        self.keys.as_ref().map(|_| "Keys exist".to_string())
+        self.keys.as_ref().map(|keys| {
+            keys.controls()
+                .iter()
+                .map(|(control, key)| format!("{}: {:X}", control.name(), key))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+    }
+
+    pub fn get_controls(&self) -> Vec<(Control, u8)> {
+        self.keys.as_ref().map(Keys::controls).unwrap_or_default()
     }
 
     pub fn get_description(&self) -> Option<&str> {
@@ -197,6 +338,10 @@ impl Rom {
         self.release.as_deref()
     }
 
+    pub fn get_rotation(&self) -> Option<u16> {
+        self.screen_rotation
+    }
+
     pub fn get_screen_rotation(&self) -> Option<String> {
         self.screen_rotation.map(|rotation| rotation.to_string())
     }
@@ -267,6 +412,32 @@ impl Program {
     }
 }
 
+// Struct for a platform that runs CHIP-8 programs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Platform {
+    id: String,
+    name: String,
+    #[serde(default)]
+    pub quirks: HashMap<String, bool>,
+}
+
+impl Platform {
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Function to load and parse the embedded platforms.json file
+fn load_embedded_platforms() -> Result<Vec<Platform>, Box<dyn std::error::Error>> {
+    let json_data = include_str!("../data/platforms.json");
+    let platforms: Vec<Platform> = serde_json::from_str(json_data)?;
+    Ok(platforms)
+}
+
 // Function to load and parse the embedded programs.json file
 fn load_embedded_programs() -> Result<Vec<Program>, Box<dyn std::error::Error>> {
     // Use the include_str! macro to embed the JSON file into the binary
@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+/// Names of addresses in a program, like its routines, from a symbol file:
+/// one address and name per line, the address in hex, with `#` or `;`
+/// starting a comment
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut names = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(address), Some(name)) = (words.next(), words.next()) else {
+                return Err(format!(
+                    "line {}: expected an address and a name",
+                    number + 1
+                ));
+            };
+            let hex = address
+                .trim_start_matches("0x")
+                .trim_start_matches("0X")
+                .trim_start_matches('$');
+            let address = u16::from_str_radix(hex, 16)
+                .map_err(|e| format!("line {}: {}: {}", number + 1, address, e))?;
+            names.insert(address, name.to_owned());
+        }
+        Ok(Self { names })
+    }
+
+    /// The name of `address`, or of the nearest symbol before it with the
+    /// offset from there, like `draw_player+6`
+    pub fn name(&self, address: u16) -> Option<String> {
+        let (&start, name) = self.names.range(..=address).next_back()?;
+        Some(if start == address {
+            name.clone()
+        } else {
+            format!("{}+{:X}", name, address - start)
+        })
+    }
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the tests and the benchmarks, with the `testing` feature.
+
+use std::path::Path;
+
+/// The ROMs bundled in `roms/`, by file name
+pub fn bundled_roms() -> Vec<(String, Vec<u8>)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms");
+    let mut roms = Vec::new();
+    for dir in ["games", "octojam titles", "tests"] {
+        for entry in std::fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "ch8") {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                roms.push((name, std::fs::read(&path).unwrap()));
+            }
+        }
+    }
+    roms.sort();
+    roms
+}
@@ -0,0 +1,314 @@
+use crate::chip8::profile::Keypad;
+use crate::keys::Control;
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Stroke, Vec2};
+use std::collections::HashMap;
+
+/// Id used for the mouse pointer, so it can press keys like a finger
+const MOUSE_TOUCH_ID: u64 = u64::MAX;
+
+/// Distance in points a finger must move before it counts as a swipe
+const SWIPE_THRESHOLD: f32 = 20.0;
+
+/// Controls used by the gamepad layout when the ROM doesn't define any
+const DEFAULT_CONTROLS: [(Control, u8); 6] = [
+    (Control::Up, 0x5),
+    (Control::Down, 0x8),
+    (Control::Left, 0x7),
+    (Control::Right, 0x9),
+    (Control::A, 0x6),
+    (Control::B, 0x4),
+];
+
+/// On-screen input modes, named after the `touchInputMode` values in the ROM database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchMode {
+    None,
+    /// 4x4 hex keypad, as laid out on the machine
+    Vip,
+    /// D-pad and A/B buttons, driven by the ROM's key controls
+    Gamepad,
+    /// The screen itself is divided into 16 touchable segments
+    Seg16,
+    /// Like `Seg16`, with the pressed segments filled in
+    Seg16Fill,
+    /// Swipe across the screen for the direction keys, tap for A
+    Swipe,
+}
+
+impl TouchMode {
+    pub const ALL: [TouchMode; 6] = [
+        TouchMode::None,
+        TouchMode::Vip,
+        TouchMode::Gamepad,
+        TouchMode::Seg16,
+        TouchMode::Seg16Fill,
+        TouchMode::Swipe,
+    ];
+
+    pub fn from_db(mode: &str) -> Option<Self> {
+        match mode {
+            "none" => Some(TouchMode::None),
+            "vip" => Some(TouchMode::Vip),
+            "gamepad" => Some(TouchMode::Gamepad),
+            "seg16" => Some(TouchMode::Seg16),
+            "seg16fill" => Some(TouchMode::Seg16Fill),
+            "swipe" => Some(TouchMode::Swipe),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TouchMode::None => "Off",
+            TouchMode::Vip => "Hex keypad",
+            TouchMode::Gamepad => "Gamepad",
+            TouchMode::Seg16 => "Screen segments",
+            TouchMode::Seg16Fill => "Screen segments (filled)",
+            TouchMode::Swipe => "Swipe",
+        }
+    }
+
+    /// Whether the mode needs a keypad panel below the screen
+    pub fn has_keypad(self) -> bool {
+        matches!(self, TouchMode::Vip | TouchMode::Gamepad)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Touch {
+    start: Pos2,
+    pos: Pos2,
+}
+
+/// Tracks fingers (and the mouse) on the screen, and the CHIP-8 keys they press
+pub struct TouchInput {
+    pub mode: TouchMode,
+    /// The machine's hex keypad, as laid out by the `Vip` mode
+    pub keypad: Keypad,
+    /// Clockwise rotation of the screen in degrees; touches are rotated to match
+    pub rotation: u16,
+    touches: HashMap<u64, Touch>,
+    pub keys_down: [bool; 16],
+}
+
+impl TouchInput {
+    pub fn new(mode: TouchMode) -> Self {
+        Self {
+            mode,
+            keypad: Keypad::Vip,
+            rotation: 0,
+            touches: HashMap::new(),
+            keys_down: [false; 16],
+        }
+    }
+
+    pub fn proc_input(&mut self, input: &egui::InputState) {
+        for event in &input.events {
+            if let egui::Event::Touch { id, phase, pos, .. } = event {
+                match phase {
+                    egui::TouchPhase::Start => {
+                        self.touches.insert(
+                            id.0,
+                            Touch {
+                                start: *pos,
+                                pos: *pos,
+                            },
+                        );
+                    }
+                    egui::TouchPhase::Move => {
+                        if let Some(touch) = self.touches.get_mut(&id.0) {
+                            touch.pos = *pos;
+                        }
+                    }
+                    egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                        self.touches.remove(&id.0);
+                    }
+                }
+            }
+        }
+
+        // the pointer follows the first finger, so only use it without touches
+        let mouse_down = input.pointer.primary_down() && !input.any_touches();
+        match (mouse_down, input.pointer.interact_pos()) {
+            (true, Some(pos)) => {
+                let touch = self
+                    .touches
+                    .entry(MOUSE_TOUCH_ID)
+                    .or_insert(Touch { start: pos, pos });
+                touch.pos = pos;
+            }
+            _ => {
+                self.touches.remove(&MOUSE_TOUCH_ID);
+            }
+        }
+    }
+
+    fn is_touched(&self, rect: Rect) -> bool {
+        self.touches.values().any(|touch| rect.contains(touch.pos))
+    }
+
+    /// Draws the hex keypad or gamepad below the screen, and presses the touched keys
+    pub fn show_keypad(&mut self, ui: &mut egui::Ui, controls: &[(Control, u8)]) {
+        self.keys_down = [false; 16];
+        let size = Vec2::new(ui.available_width(), ui.available_width().min(240.0) * 0.6);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+
+        match self.mode {
+            TouchMode::Vip => {
+                let pad = Rect::from_center_size(rect.center(), Vec2::splat(rect.height()));
+                let key_size = pad.size() / 4.0;
+                for (row, keys) in self.keypad.layout().iter().enumerate() {
+                    for (col, &chip8_key) in keys.iter().enumerate() {
+                        let min = pad.min + Vec2::new(col as f32, row as f32) * key_size;
+                        let key_rect = Rect::from_min_size(min, key_size).shrink(2.0);
+                        self.show_key(ui, key_rect, &format!("{:X}", chip8_key), chip8_key);
+                    }
+                }
+            }
+            TouchMode::Gamepad => {
+                let controls = if controls.is_empty() {
+                    &DEFAULT_CONTROLS[..]
+                } else {
+                    controls
+                };
+                // the d-pad turns with the screen, so up stays up on the display
+                let unit = rect.height() / 3.0;
+                let rotation = self.rotation;
+                let turn = |offset: Vec2| rotate(offset, rotation);
+                let dpad = Pos2::new(rect.left() + 1.5 * unit, rect.center().y);
+                let buttons = Pos2::new(rect.right() - 1.5 * unit, rect.center().y);
+                for &(control, chip8_key) in controls {
+                    let center = match control {
+                        Control::Up => dpad + turn(Vec2::new(0.0, -unit)),
+                        Control::Down => dpad + turn(Vec2::new(0.0, unit)),
+                        Control::Left => dpad + turn(Vec2::new(-unit, 0.0)),
+                        Control::Right => dpad + turn(Vec2::new(unit, 0.0)),
+                        Control::A => buttons + Vec2::new(unit * 0.6, -unit * 0.4),
+                        Control::B => buttons - Vec2::new(unit * 0.6, -unit * 0.4),
+                        // the second player gets the right-hand side
+                        Control::Player2Up => buttons - Vec2::new(0.0, unit),
+                        Control::Player2Down => buttons + Vec2::new(0.0, unit),
+                    };
+                    let key_rect = Rect::from_center_size(center, Vec2::splat(unit)).shrink(2.0);
+                    self.show_key(ui, key_rect, control.name(), chip8_key as usize & 0xf);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn show_key(&mut self, ui: &egui::Ui, rect: Rect, label: &str, chip8_key: usize) {
+        let pressed = self.is_touched(rect);
+        if pressed {
+            self.keys_down[chip8_key] = true;
+        }
+
+        let visuals = ui.visuals();
+        let fill = if pressed {
+            visuals.selection.bg_fill
+        } else {
+            visuals.widgets.inactive.bg_fill
+        };
+        let painter = ui.painter();
+        painter.rect_filled(rect, 4.0, fill);
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            label,
+            FontId::proportional(rect.height() * 0.4),
+            visuals.text_color(),
+        );
+    }
+
+    /// Handles touches on the emulator screen itself, for the segment and swipe modes
+    pub fn show_overlay(&mut self, ui: &egui::Ui, screen: Rect, controls: &[(Control, u8)]) {
+        if self.mode.has_keypad() {
+            return;
+        }
+        self.keys_down = [false; 16];
+
+        match self.mode {
+            TouchMode::Seg16 | TouchMode::Seg16Fill => {
+                // map the touches to segments on the display, before rotation
+                for touch in self.touches.values() {
+                    if screen.contains(touch.pos) {
+                        let pos = screen_to_display(touch.pos, screen, self.rotation);
+                        let col = ((pos.x * 4.0) as usize).min(3);
+                        let row = ((pos.y * 4.0) as usize).min(3);
+                        self.keys_down[row * 4 + col] = true;
+                    }
+                }
+
+                let stroke = Stroke::new(1.0, Color32::from_white_alpha(32));
+                for row in 0..4 {
+                    for col in 0..4 {
+                        let min = Pos2::new(col as f32, row as f32) / 4.0;
+                        let max = min + Vec2::splat(0.25);
+                        let rect = Rect::from_two_pos(
+                            display_to_screen(min, screen, self.rotation),
+                            display_to_screen(max, screen, self.rotation),
+                        );
+                        if self.mode == TouchMode::Seg16Fill && self.keys_down[row * 4 + col] {
+                            ui.painter()
+                                .rect_filled(rect, 0.0, Color32::from_white_alpha(48));
+                        }
+                        ui.painter().rect_stroke(rect, 0.0, stroke);
+                    }
+                }
+            }
+            TouchMode::Swipe => {
+                let controls = if controls.is_empty() {
+                    &DEFAULT_CONTROLS[..]
+                } else {
+                    controls
+                };
+                for touch in self.touches.values() {
+                    if !screen.contains(touch.start) {
+                        continue;
+                    }
+                    let delta = rotate(touch.pos - touch.start, 360 - self.rotation);
+                    let control = if delta.length() < SWIPE_THRESHOLD {
+                        Control::A
+                    } else if delta.x.abs() > delta.y.abs() {
+                        if delta.x > 0.0 {
+                            Control::Right
+                        } else {
+                            Control::Left
+                        }
+                    } else if delta.y > 0.0 {
+                        Control::Down
+                    } else {
+                        Control::Up
+                    };
+                    if let Some((_, chip8_key)) = controls.iter().find(|(c, _)| *c == control) {
+                        self.keys_down[*chip8_key as usize & 0xf] = true;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Rotates a vector clockwise by 0, 90, 180 or 270 degrees
+pub fn rotate(v: Vec2, rotation: u16) -> Vec2 {
+    match rotation % 360 {
+        90 => Vec2::new(-v.y, v.x),
+        180 => -v,
+        270 => Vec2::new(v.y, -v.x),
+        _ => v,
+    }
+}
+
+/// Maps a position on the display, from (0, 0) to (1, 1), onto the rotated screen
+pub fn display_to_screen(pos: Pos2, screen: Rect, rotation: u16) -> Pos2 {
+    let centered = rotate(pos - Pos2::new(0.5, 0.5), rotation);
+    screen.lerp_inside(centered + Vec2::splat(0.5))
+}
+
+/// Maps a position on the rotated screen to the display, from (0, 0) to (1, 1)
+pub fn screen_to_display(pos: Pos2, screen: Rect, rotation: u16) -> Pos2 {
+    let normalized = (pos - screen.min) / screen.size();
+    let centered = rotate(normalized - Vec2::splat(0.5), 360 - rotation);
+    Pos2::new(0.5, 0.5) + centered
+}
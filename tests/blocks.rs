@@ -0,0 +1,94 @@
+//! Runs the bundled ROMs on the interpreter and on the block compiler side
+//! by side, with random keys, and checks that they never diverge.
+
+use chippie::chip8::blocks::Engine;
+use chippie::chip8::profile::CHIP_8;
+use chippie::chip8::quirks::Quirks;
+use chippie::chip8::shadow::Shadow;
+use chippie::chip8::Cpu;
+use chippie::testing::bundled_roms;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+
+const FRAMES: usize = 300;
+
+const TICKS_PER_FRAME: u16 = 200;
+
+fn check(quirks: Quirks) {
+    let mut keys = StdRng::seed_from_u64(1);
+    for (name, rom) in bundled_roms() {
+        let cpu = |engine| {
+            let mut cpu = Cpu::with_profile(CHIP_8);
+            cpu.engine = engine;
+            cpu.quirks = quirks;
+            cpu.bus.load_rom(&rom);
+            cpu
+        };
+        let mut main = cpu(Engine::Interpreter);
+        let mut shadow = Shadow::new(&mut main, cpu(Engine::Blocks), 0);
+
+        for _ in 0..FRAMES {
+            // keys change now and then, and are held for a while
+            if keys.gen_ratio(1, 8) {
+                let down: u16 = keys.gen::<u16>() & keys.gen::<u16>();
+                for (key, key_down) in main.keys_down.iter_mut().enumerate() {
+                    *key_down = down & (1 << key) != 0;
+                }
+            }
+            shadow.run_frame(&mut main, TICKS_PER_FRAME);
+        }
+        assert!(
+            shadow.divergence.is_none(),
+            "{}: {:?}",
+            name,
+            shadow.divergence
+        );
+    }
+}
+
+#[test]
+fn default_quirks() {
+    check(Quirks::default());
+}
+
+#[test]
+fn vblank() {
+    check(Quirks {
+        vblank: true,
+        ..Quirks::default()
+    });
+}
+
+#[test]
+fn super_chip_quirks() {
+    check(Quirks {
+        shift: true,
+        memory_leave_i_unchanged: true,
+        jump: true,
+        logic: false,
+        wrap: true,
+        key_wait_press: true,
+        big_sprites: true,
+        ..Quirks::default()
+    });
+}
+
+#[test]
+fn clones_compile_their_blocks_again() {
+    // the clone starts without the original's blocks, and runs the same
+    let rom = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/tests/3-corax+.ch8"))
+        .unwrap();
+    let mut main = Cpu::with_profile(CHIP_8);
+    main.engine = Engine::Blocks;
+    main.bus.load_rom(&rom);
+    for _ in 0..10 {
+        main.ticks(TICKS_PER_FRAME);
+    }
+    let mut clone = main.clone();
+    for _ in 0..FRAMES {
+        main.ticks(TICKS_PER_FRAME);
+        clone.ticks(TICKS_PER_FRAME);
+    }
+    assert_eq!(main.difference(&clone), None);
+}
@@ -0,0 +1,253 @@
+//! The CDP1802 that runs machine code routines: arithmetic with borrow,
+//! branches and their pages, subroutines and waiting for interrupts, and
+//! routines called with 0NNN that never return.
+
+use chippie::chip8::cdp1802::{Cdp1802, Cdp1802Bus};
+use chippie::chip8::profile::VIP_HYBRID;
+use chippie::chip8::Cpu;
+
+/// 64K of RAM and nothing else
+struct Ram(Vec<u8>);
+
+impl Cdp1802Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.0[address as usize] = data;
+    }
+}
+
+/// A CDP1802 just reset, with `program` at `at`, where R0 points
+fn load(at: u16, program: &[u8]) -> (Cdp1802, Ram) {
+    let mut ram = Ram(vec![0; 0x10000]);
+    ram.0[at as usize..at as usize + program.len()].copy_from_slice(program);
+    let mut cpu = Cdp1802::new();
+    cpu.r[0] = at;
+    (cpu, ram)
+}
+
+/// D and DF after one instruction from D = `d` and DF = `df`
+fn arithmetic(program: &[u8], d: u8, df: bool) -> (u8, bool) {
+    let (mut cpu, mut ram) = load(0, program);
+    cpu.d = d;
+    cpu.df = df;
+    cpu.step(&mut ram);
+    (cpu.d, cpu.df)
+}
+
+#[test]
+fn adc_adds_the_carry() {
+    // ADCI
+    assert_eq!(arithmetic(&[0x7C, 0x20], 0x10, true), (0x31, false));
+    assert_eq!(arithmetic(&[0x7C, 0x00], 0xFF, true), (0x00, true));
+    assert_eq!(arithmetic(&[0x7C, 0x01], 0xFE, false), (0xFF, false));
+}
+
+#[test]
+fn sdb_subtracts_d_and_the_borrow() {
+    // SDBI: DF is clear after a borrow, and a clear DF borrows one
+    assert_eq!(arithmetic(&[0x7D, 0x10], 0x05, true), (0x0B, true));
+    assert_eq!(arithmetic(&[0x7D, 0x10], 0x05, false), (0x0A, true));
+    assert_eq!(arithmetic(&[0x7D, 0x05], 0x10, true), (0xF5, false));
+    assert_eq!(arithmetic(&[0x7D, 0x10], 0x10, false), (0xFF, false));
+}
+
+#[test]
+fn smb_subtracts_memory_and_the_borrow() {
+    // SMBI
+    assert_eq!(arithmetic(&[0x7F, 0x05], 0x10, true), (0x0B, true));
+    assert_eq!(arithmetic(&[0x7F, 0x10], 0x10, false), (0xFF, false));
+    assert_eq!(arithmetic(&[0x7F, 0x0F], 0x10, false), (0x00, true));
+}
+
+#[test]
+fn sdb_and_smb_read_via_x() {
+    let (mut cpu, mut ram) = load(0, &[0x75, 0x77]);
+    cpu.x = 1;
+    cpu.r[1] = 0x100;
+    ram.0[0x100] = 0x30;
+    cpu.d = 0x10;
+    cpu.df = false;
+    // SDB: 30 - 10 - 1
+    cpu.step(&mut ram);
+    assert_eq!((cpu.d, cpu.df), (0x1F, true));
+    // SMB: 1F - 30
+    cpu.step(&mut ram);
+    assert_eq!((cpu.d, cpu.df), (0xEF, false));
+}
+
+#[test]
+fn short_branches_stay_in_the_page_of_their_target_byte() {
+    // BR with its target byte at the end of the page
+    let (mut cpu, mut ram) = load(0x01FE, &[0x30, 0x40]);
+    assert_eq!(cpu.step(&mut ram), 2);
+    assert_eq!(cpu.r[0], 0x0140);
+
+    // the target byte is the first of the next page
+    let (mut cpu, mut ram) = load(0x01FF, &[0x30, 0x40]);
+    cpu.step(&mut ram);
+    assert_eq!(cpu.r[0], 0x0240);
+}
+
+#[test]
+fn short_branches_not_taken_skip_their_target_byte() {
+    // BNZ with D = 0
+    let (mut cpu, mut ram) = load(0x0100, &[0x3A, 0x40]);
+    cpu.step(&mut ram);
+    assert_eq!(cpu.r[0], 0x0102);
+}
+
+#[test]
+fn long_branches_go_anywhere_in_three_cycles() {
+    // LBR
+    let (mut cpu, mut ram) = load(0x01FE, &[0xC0, 0x12, 0x34]);
+    assert_eq!(cpu.step(&mut ram), 3);
+    assert_eq!(cpu.r[0], 0x1234);
+
+    // LBNZ with D = 0 skips the address
+    let (mut cpu, mut ram) = load(0x0100, &[0xCA, 0x12, 0x34]);
+    assert_eq!(cpu.step(&mut ram), 3);
+    assert_eq!(cpu.r[0], 0x0103);
+}
+
+#[test]
+fn long_skips_skip_two_bytes() {
+    // LSKP
+    let (mut cpu, mut ram) = load(0x0100, &[0xC8]);
+    cpu.step(&mut ram);
+    assert_eq!(cpu.r[0], 0x0103);
+
+    // LSZ with D != 0 doesn't
+    let (mut cpu, mut ram) = load(0x0100, &[0xCE]);
+    cpu.d = 1;
+    cpu.step(&mut ram);
+    assert_eq!(cpu.r[0], 0x0101);
+
+    // NOP takes three cycles, too
+    let (mut cpu, mut ram) = load(0x0100, &[0xC4]);
+    assert_eq!(cpu.step(&mut ram), 3);
+    assert_eq!(cpu.r[0], 0x0101);
+}
+
+#[test]
+fn mark_saves_x_and_p_for_ret() {
+    // MARK; SEX R2; INC R2; RET
+    let (mut cpu, mut ram) = load(0x0100, &[0x79, 0xE2, 0x12, 0x70]);
+    cpu.x = 5;
+    cpu.r[2] = 0x01FF;
+    cpu.ie = false;
+
+    cpu.step(&mut ram);
+    assert_eq!(cpu.t, 0x50);
+    assert_eq!(ram.0[0x01FF], 0x50);
+    assert_eq!(cpu.x, 0);
+    assert_eq!(cpu.r[2], 0x01FE);
+
+    for _ in 0..3 {
+        cpu.step(&mut ram);
+    }
+    assert_eq!((cpu.x, cpu.p), (5, 0));
+    assert_eq!(cpu.r[2], 0x0200);
+    assert!(cpu.ie);
+}
+
+#[test]
+fn dis_restores_x_and_p_with_interrupts_off() {
+    // SEX R0; DIS with X and P in the next byte
+    let (mut cpu, mut ram) = load(0x0100, &[0xE0, 0x71, 0x23]);
+    cpu.step(&mut ram);
+    cpu.step(&mut ram);
+    assert_eq!((cpu.x, cpu.p), (2, 3));
+    assert!(!cpu.ie);
+}
+
+#[test]
+fn idl_waits_for_an_interrupt() {
+    let (mut cpu, mut ram) = load(0x0100, &[0x00]);
+    cpu.x = 5;
+    cpu.step(&mut ram);
+    assert!(cpu.idle);
+    assert_eq!(cpu.step(&mut ram), 1);
+    assert_eq!(cpu.r[0], 0x0101);
+
+    cpu.interrupt();
+    assert!(!cpu.idle);
+    assert_eq!((cpu.x, cpu.p, cpu.t), (2, 1, 0x50));
+    assert!(!cpu.ie);
+}
+
+#[test]
+fn idl_with_interrupts_off_waits_for_dma() {
+    let (mut cpu, mut ram) = load(0x0100, &[0x00]);
+    cpu.ie = false;
+    cpu.step(&mut ram);
+    cpu.interrupt();
+    assert!(cpu.idle);
+    cpu.dma_out(&mut ram);
+    assert!(!cpu.idle);
+}
+
+/// Calls the routine at 0204 with 0NNN, then loops
+fn call(routine: &[u8]) -> Cpu {
+    let mut rom = vec![0x02, 0x04, 0x12, 0x02];
+    rom.extend(routine);
+    let mut cpu = Cpu::with_profile(VIP_HYBRID);
+    cpu.bus.load_rom(&rom);
+    cpu.ticks(10);
+    cpu
+}
+
+#[test]
+fn routines_return_with_sep_r4() {
+    // RA, which is I, becomes 1234
+    let cpu = call(&[0xF8, 0x12, 0xBA, 0xF8, 0x34, 0xAA, 0xD4]);
+    assert_eq!(cpu.error(), None);
+    assert_eq!(cpu.i(), 0x1234);
+    assert_eq!(cpu.pc, 0x0202);
+}
+
+#[test]
+fn routines_that_never_return_stop_the_program() {
+    // BR to itself
+    let cpu = call(&[0x30, 0x04]);
+    assert_eq!(cpu.error(), Some("Machine code at 0204 did not return"));
+    assert_eq!(cpu.pc, 0x0200);
+}
+
+#[test]
+fn routines_idle_with_interrupts_off_stop_the_program() {
+    // SEX R3; DIS with X = 2 and P = 3; IDL
+    let cpu = call(&[0xE3, 0x71, 0x23, 0x00]);
+    assert_eq!(
+        cpu.error(),
+        Some("Machine code at 0204 went idle with interrupts off")
+    );
+}
+
+#[test]
+fn the_cycle_budget_is_shared_by_the_routines_of_a_frame() {
+    // three calls of a routine that counts R7 down from FF00, about 390000
+    // cycles each; only two fit in a frame
+    let mut rom = vec![0x02, 0x08, 0x02, 0x08, 0x02, 0x08, 0x12, 0x06];
+    rom.extend([0xF8, 0xFF, 0xB7, 0x27, 0x97, 0x3A, 0x0B, 0xD4]);
+    let mut cpu = Cpu::with_profile(VIP_HYBRID);
+    cpu.bus.load_rom(&rom);
+    cpu.ticks(10);
+    assert_eq!(cpu.error(), Some("Machine code at 0208 did not return"));
+    assert_eq!(cpu.pc, 0x0204);
+}
+
+#[test]
+fn the_cycle_budget_is_renewed_every_frame() {
+    // the same routine, once a frame
+    let mut rom = vec![0x02, 0x08, 0x00, 0xE0, 0x12, 0x00, 0x00, 0x00];
+    rom.extend([0xF8, 0xFF, 0xB7, 0x27, 0x97, 0x3A, 0x0B, 0xD4]);
+    let mut cpu = Cpu::with_profile(VIP_HYBRID);
+    cpu.bus.load_rom(&rom);
+    for _ in 0..10 {
+        cpu.ticks(3);
+    }
+    assert_eq!(cpu.error(), None);
+}
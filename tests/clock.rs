@@ -0,0 +1,64 @@
+//! The clock must keep the instruction and timer rates however the time
+//! passed is cut up.
+
+use chippie::chip8::clock::Clock;
+use std::time::Duration;
+
+/// Ticks and instructions over `steps` steps of `step` each
+fn run(clock: &mut Clock, step: Duration, steps: u32) -> (u32, u32) {
+    let (mut ticks, mut instructions) = (0, 0);
+    for _ in 0..steps {
+        for _ in 0..clock.advance(step) {
+            ticks += 1;
+            instructions += clock.tick_instructions() as u32;
+        }
+    }
+    (ticks, instructions)
+}
+
+#[test]
+fn rates_hold_for_any_host_refresh_rate() {
+    for refresh_rate in [30, 50, 60, 75, 144, 240, 1000] {
+        let mut clock = Clock::new(1000, 60);
+        let step = Duration::from_secs(1) / refresh_rate;
+        let (ticks, instructions) = run(&mut clock, step, refresh_rate * 10);
+        // a tick may be left over from the rounding of the steps
+        assert!(
+            (599..=600).contains(&ticks),
+            "{}Hz: {}",
+            refresh_rate,
+            ticks
+        );
+        assert!(
+            instructions.abs_diff(ticks * 1000 / 60) <= 1,
+            "{}Hz: {} in {} ticks",
+            refresh_rate,
+            instructions,
+            ticks
+        );
+    }
+}
+
+#[test]
+fn instructions_and_timers_are_independent() {
+    let mut clock = Clock::new(700, 60);
+    assert_eq!(run(&mut clock, Duration::from_secs(1), 1), (60, 700));
+
+    clock.timer_rate = 50;
+    assert_eq!(run(&mut clock, Duration::from_secs(1), 1), (50, 700));
+}
+
+#[test]
+fn a_long_stall_runs_all_its_ticks() {
+    let mut clock = Clock::with_tickrate(10);
+    assert_eq!(clock.advance(Duration::from_secs(3600)), 3600 * 60);
+}
+
+#[test]
+fn until_tick() {
+    let mut clock = Clock::with_tickrate(10);
+    clock.advance(Duration::from_millis(10));
+    let left = clock.until_tick();
+    assert_eq!(clock.advance(left - Duration::from_nanos(1)), 0);
+    assert_eq!(clock.advance(Duration::from_nanos(1)), 1);
+}
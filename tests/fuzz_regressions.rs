@@ -0,0 +1,91 @@
+//! ROMs that made the instruction core panic, found by the fuzz targets in
+//! `fuzz/`. Each runs for a while and must keep the interpreter's invariants.
+
+use chippie::chip8::profile::{MachineProfile, CHIP_8, XO_CHIP};
+use chippie::chip8::Cpu;
+
+/// Runs `rom` for `frames` frames with the keys from `keys`, checking the
+/// invariants after each frame
+fn run(profile: MachineProfile, rom: &[u8], frames: usize, keys: impl Fn(usize) -> u16) -> Cpu {
+    let mut cpu = Cpu::with_profile(profile);
+    cpu.seed_rng(0);
+    cpu.bus.load_rom(rom);
+    for frame in 0..frames {
+        for (key, key_down) in cpu.keys_down.iter_mut().enumerate() {
+            *key_down = keys(frame) & (1 << key) != 0;
+        }
+        cpu.ticks(20);
+        assert!(
+            (cpu.pc as usize) < cpu.bus.memory().len(),
+            "PC {:04X} out of memory",
+            cpu.pc
+        );
+        assert!(cpu.stack().len() <= cpu.stack_depth);
+    }
+    cpu
+}
+
+fn no_keys(_frame: usize) -> u16 {
+    0
+}
+
+#[test]
+fn return_without_call() {
+    // 00EE
+    let cpu = run(CHIP_8, &[0x00, 0xee], 2, no_keys);
+    assert!(cpu.stack().is_empty());
+}
+
+#[test]
+fn endless_recursion() {
+    // 2200 calls itself
+    let cpu = run(CHIP_8, &[0x22, 0x00], 10, no_keys);
+    assert_eq!(cpu.stack().len(), cpu.stack_depth);
+}
+
+#[test]
+fn index_overflow() {
+    // V0 = FF, I = FFF, then I += V0 forever
+    let cpu = run(
+        CHIP_8,
+        &[0x60, 0xff, 0xaf, 0xff, 0xf0, 0x1e, 0x12, 0x04],
+        500,
+        no_keys,
+    );
+    assert_ne!(cpu.i(), 0);
+}
+
+#[test]
+fn load_store_and_bcd_at_the_top_of_the_index() {
+    // I = FFF, I += FF until I wraps, with FF55, FF65 and F033 on the way
+    let rom = [
+        0x60, 0xff, 0xaf, 0xff, 0xff, 0x55, 0xff, 0x65, 0xf0, 0x33, 0xf0, 0x1e, 0x12, 0x04,
+    ];
+    run(CHIP_8, &rom, 500, no_keys);
+}
+
+#[test]
+fn jump_past_the_end_of_memory() {
+    // V0 = FF, BFFF
+    run(CHIP_8, &[0x60, 0xff, 0xbf, 0xff], 2, no_keys);
+}
+
+#[test]
+fn running_off_the_end_of_memory() {
+    // the zeroes after the ROM run until PC wraps around, on 4K and 64K machines
+    run(CHIP_8, &[0x00, 0x00], 300, no_keys);
+    run(XO_CHIP, &[0x00, 0x00], 3300, no_keys);
+}
+
+#[test]
+fn wait_for_key_at_the_end_of_memory() {
+    // store F00A at FFE and jump there; key 0 is pressed and released, and
+    // the program goes on at 000
+    let rom = [0x60, 0xf0, 0x61, 0x0a, 0xaf, 0xfe, 0xf1, 0x55, 0x1f, 0xfe];
+    run(CHIP_8, &rom, 20, |frame| (frame == 2) as u16);
+}
+
+#[test]
+fn rom_larger_than_memory() {
+    run(CHIP_8, &[0x12; 0x2000], 1, no_keys);
+}
@@ -0,0 +1,103 @@
+//! FX0A against Timendus' keypad test, which waits for a key with FX0A when
+//! its third test is chosen, and then tells whether the key was released.
+
+use chippie::chip8::profile::CHIP_8;
+use chippie::chip8::{Cpu, Machine};
+
+/// The result mark the keypad test draws above its message
+const ALL_GOOD: [&str; 3] = ["#.#", "##.", "#.."];
+
+fn keypad_test(key_wait_press: bool) -> Cpu {
+    let rom = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/roms/tests/6-keypad.ch8"
+    ))
+    .unwrap();
+    let mut cpu = Cpu::with_profile(CHIP_8);
+    cpu.quirks.key_wait_press = key_wait_press;
+    cpu.bus.load_rom(&rom);
+    frames(&mut cpu, None, 60);
+    // choose the FX0A test
+    frames(&mut cpu, Some(3), 10);
+    frames(&mut cpu, None, 30);
+    cpu
+}
+
+/// Runs `count` frames with `key` held
+fn frames(cpu: &mut Cpu, key: Option<usize>, count: usize) {
+    for _ in 0..count {
+        for (index, key_down) in cpu.keys_down.iter_mut().enumerate() {
+            *key_down = Some(index) == key;
+        }
+        cpu.ticks(1000);
+    }
+}
+
+fn mark(cpu: &Cpu) -> [String; 3] {
+    [9, 10, 11].map(|y| {
+        (30..33)
+            .map(|x| if cpu.bus.gpu.pixel(x, y) { '#' } else { '.' })
+            .collect()
+    })
+}
+
+#[test]
+fn fx0a_waits_for_the_key_to_be_released() {
+    let mut cpu = keypad_test(false);
+    let waiting = cpu.bus.gpu.buffer.clone();
+    frames(&mut cpu, Some(5), 30);
+    assert_eq!(cpu.bus.gpu.buffer, waiting, "went on before the release");
+    frames(&mut cpu, None, 30);
+    assert_eq!(mark(&cpu), ALL_GOOD);
+}
+
+#[test]
+fn fx0a_goes_on_at_the_key_press() {
+    let mut cpu = keypad_test(true);
+    frames(&mut cpu, Some(5), 30);
+    // the key taken at the press reads as released while it is held
+    assert_eq!(mark(&cpu), ALL_GOOD);
+}
+
+#[test]
+fn a_held_key_is_taken_by_one_fx0a_only() {
+    // wait for a key into V1 and count it in V2, twice, and loop
+    let rom = [0xf1, 0x0a, 0x72, 0x01, 0xf1, 0x0a, 0x72, 0x01, 0x12, 0x08];
+    let mut cpu = Cpu::with_profile(CHIP_8);
+    cpu.quirks.key_wait_press = true;
+    cpu.bus.load_rom(&rom);
+    frames(&mut cpu, Some(7), 10);
+    assert_eq!(cpu.v()[2], 1);
+    frames(&mut cpu, None, 1);
+    frames(&mut cpu, Some(7), 1);
+    assert_eq!(cpu.v()[2], 2);
+}
+
+#[test]
+fn the_sound_timer_runs_on_through_a_key_wait() {
+    // V0 = 30, sound timer = V0, wait for a key into V1, and loop
+    let rom = [0x60, 30, 0xf0, 0x18, 0xf1, 0x0a, 0x12, 0x06];
+    let mut cpu = Cpu::with_profile(CHIP_8);
+    cpu.bus.load_rom(&rom);
+    frames(&mut cpu, None, 10);
+    frames(&mut cpu, Some(7), 1);
+    frames(&mut cpu, None, 1);
+    assert_eq!(cpu.v()[1], 7);
+    assert_eq!(cpu.sound_timer, 30 - 12);
+}
+
+#[test]
+fn the_click_is_optional() {
+    for key_click in [false, true] {
+        // wait for a key into V1, and loop
+        let rom = [0xf1, 0x0a, 0x12, 0x02];
+        let mut cpu = Cpu::with_profile(CHIP_8);
+        cpu.key_click = key_click;
+        cpu.bus.load_rom(&rom);
+        frames(&mut cpu, Some(7), 1);
+        assert_eq!(cpu.is_beeping(), key_click);
+        assert_eq!(cpu.sound_timer, 0);
+        frames(&mut cpu, None, 10);
+        assert!(!cpu.is_beeping());
+    }
+}
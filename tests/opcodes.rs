@@ -0,0 +1,505 @@
+//! Runs single instructions on random machine states under random quirks, and
+//! compares the result with a small reference specification of each instruction.
+
+use chippie::chip8::gpu::{HIRES, LORES};
+use chippie::chip8::profile::CHIP_8;
+use chippie::chip8::quirks::Quirks;
+use chippie::chip8::Cpu;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Random states tried per test
+const CASES: usize = 20_000;
+
+const MEMORY_SIZE: usize = 0x1000;
+
+/// What an instruction can change
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    stack: Vec<u16>,
+    memory: Vec<u8>,
+    /// The display rows, leftmost pixel in the most significant bit
+    screen: Vec<u128>,
+    hires: bool,
+    delay: u8,
+    sound: u8,
+    keys: [bool; 16],
+}
+
+impl State {
+    fn random(rng: &mut StdRng, opcode: u16) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        rng.fill(&mut memory[..]);
+        // anywhere in the program space, leaving room to skip an instruction
+        let pc = rng.gen_range(0x100..0x7fe) * 2;
+        memory[pc as usize..pc as usize + 2].copy_from_slice(&opcode.to_be_bytes());
+        let hires = rng.gen();
+        let screen = if hires {
+            (0..HIRES.1).map(|_| rng.gen()).collect()
+        } else {
+            (0..LORES.1)
+                .map(|_| (rng.gen::<u64>() as u128) << 64)
+                .collect()
+        };
+        Self {
+            pc,
+            i: rng.gen(),
+            v: rng.gen(),
+            stack: Vec::new(),
+            memory,
+            screen,
+            hires,
+            delay: rng.gen(),
+            sound: rng.gen(),
+            keys: rng.gen(),
+        }
+    }
+
+    fn of(cpu: &Cpu) -> Self {
+        Self {
+            pc: cpu.pc,
+            i: cpu.i(),
+            v: *cpu.v(),
+            stack: cpu.stack().to_vec(),
+            memory: cpu.bus.memory().to_vec(),
+            screen: cpu.bus.gpu.buffer.clone(),
+            hires: cpu.bus.gpu.is_hires(),
+            delay: cpu.delay_timer(),
+            sound: cpu.sound_timer,
+            keys: cpu.keys_down,
+        }
+    }
+
+    fn cpu(&self, quirks: Quirks) -> Cpu {
+        let mut cpu = Cpu::with_profile(CHIP_8);
+        cpu.quirks = quirks;
+        cpu.bus.load(0, &self.memory);
+        cpu.pc = self.pc;
+        cpu.set_i(self.i);
+        *cpu.v_mut() = self.v;
+        if self.hires {
+            cpu.bus.gpu.set_size(HIRES.0, HIRES.1);
+        }
+        cpu.bus.gpu.buffer = self.screen.clone();
+        cpu.set_delay_timer(self.delay);
+        cpu.sound_timer = self.sound;
+        cpu.keys_down = self.keys;
+        cpu
+    }
+
+    fn poke(&mut self, address: u16, data: u8) {
+        self.memory[address as usize % MEMORY_SIZE] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize % MEMORY_SIZE]
+    }
+}
+
+/// The reference: what `opcode` does to `before`, written from the
+/// instruction set's documentation rather than from the interpreter
+fn reference(before: &State, opcode: u16, quirks: Quirks) -> State {
+    let mut s = before.clone();
+    let x = (opcode >> 8 & 0xf) as usize;
+    let y = (opcode >> 4 & 0xf) as usize;
+    let n = opcode & 0xf;
+    let nn = (opcode & 0xff) as u8;
+    let nnn = opcode & 0xfff;
+    let (vx, vy) = (s.v[x], s.v[y]);
+    let next = s.pc + 2;
+    let skip_if = |condition: bool| if condition { next + 2 } else { next };
+    s.pc = next;
+
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00e0 => s.screen.fill(0),
+            0x00ee => s.pc = s.stack.pop().unwrap(),
+            // switching the resolution clears the screen
+            0x00fe | 0x00ff => {
+                s.hires = opcode == 0x00ff;
+                let (_, height) = if s.hires { HIRES } else { LORES };
+                s.screen = vec![0; height];
+            }
+            _ => unreachable!(),
+        },
+        0x1 => s.pc = nnn,
+        0x2 => {
+            s.stack.push(next);
+            s.pc = nnn;
+        }
+        0x3 => s.pc = skip_if(vx == nn),
+        0x4 => s.pc = skip_if(vx != nn),
+        0x5 => s.pc = skip_if(vx == vy),
+        0x6 => s.v[x] = nn,
+        0x7 => s.v[x] = vx.wrapping_add(nn),
+        0x8 => {
+            // the flag is written last, so it wins when X is F
+            let (result, flag) = match n {
+                0x0 => (vy, None),
+                0x1 => (vx | vy, quirks.logic.then_some(0)),
+                0x2 => (vx & vy, quirks.logic.then_some(0)),
+                0x3 => (vx ^ vy, quirks.logic.then_some(0)),
+                0x4 => (
+                    vx.wrapping_add(vy),
+                    Some((vx as u16 + vy as u16 > 0xff) as u8),
+                ),
+                0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+                0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+                0x6 | 0xe => {
+                    let source = if quirks.shift { vx } else { vy };
+                    if n == 0x6 {
+                        (source >> 1, Some(source & 1))
+                    } else {
+                        (source << 1, Some(source >> 7))
+                    }
+                }
+                _ => unreachable!(),
+            };
+            s.v[x] = result;
+            if let Some(flag) = flag {
+                s.v[0xf] = flag;
+            }
+        }
+        0x9 => s.pc = skip_if(vx != vy),
+        0xa => s.i = nnn,
+        0xb => {
+            let offset = if quirks.jump { vx } else { s.v[0] };
+            s.pc = (nnn + offset as u16) % MEMORY_SIZE as u16;
+        }
+        0xd => draw(&mut s, vx, vy, n as u8, quirks),
+        0xe => {
+            let down = s.keys[(vx & 0xf) as usize];
+            s.pc = match nn {
+                0x9e => skip_if(down),
+                0xa1 => skip_if(!down),
+                _ => unreachable!(),
+            };
+        }
+        0xf => match nn {
+            0x07 => s.v[x] = s.delay,
+            0x15 => s.delay = vx,
+            0x18 => s.sound = vx,
+            0x1e => s.i = s.i.wrapping_add(vx as u16),
+            0x29 => s.i = CHIP_8.font_address + (vx & 0xf) as u16 * 5,
+            // SUPER-CHIP's big font has the digits 0 to 9, 10 rows each
+            0x30 => s.i = CHIP_8.big_font_address + (vx & 0xf).min(9) as u16 * 10,
+            0x33 => {
+                for (offset, digit) in [vx / 100, vx / 10 % 10, vx % 10].into_iter().enumerate() {
+                    s.poke(s.i.wrapping_add(offset as u16), digit);
+                }
+            }
+            0x55 | 0x65 => {
+                for r in 0..=x {
+                    let address = s.i.wrapping_add(r as u16);
+                    if nn == 0x55 {
+                        s.poke(address, s.v[r]);
+                    } else {
+                        s.v[r] = s.peek(address);
+                    }
+                }
+                if !quirks.memory_leave_i_unchanged {
+                    let count = if quirks.memory_increment_by_x {
+                        x
+                    } else {
+                        x + 1
+                    };
+                    s.i = s.i.wrapping_add(count as u16);
+                }
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+    s
+}
+
+/// DXYN: N rows of 8 pixels, or 16x16 for DXY0 with SUPER-CHIP sprites
+/// or in hires, flipped onto the screen from (VX, VY)
+fn draw(s: &mut State, vx: u8, vy: u8, n: u8, quirks: Quirks) {
+    let (width, height) = if s.hires { HIRES } else { LORES };
+    let big = n == 0 && (quirks.big_sprites || s.hires);
+    let (rows, bits) = if big { (16, 16) } else { (n as u16, 8) };
+    // the start wraps around; what crosses the edges is clipped or wraps
+    let (left, top) = (vx as usize % width, vy as usize % height);
+    let (mut collided, mut clipped) = (0, 0);
+    for row in 0..rows {
+        let data = if big {
+            u16::from_be_bytes([
+                s.peek(s.i.wrapping_add(row * 2)),
+                s.peek(s.i.wrapping_add(row * 2 + 1)),
+            ])
+        } else {
+            s.peek(s.i.wrapping_add(row)) as u16
+        };
+        let mut y = top + row as usize;
+        if y >= height {
+            if !quirks.wrap {
+                clipped += 1;
+                continue;
+            }
+            y %= height;
+        }
+        let mut collision = false;
+        for bit in 0..bits {
+            if data & 1 << (bits - 1 - bit) == 0 {
+                continue;
+            }
+            let mut x = left + bit as usize;
+            if x >= width {
+                if !quirks.wrap {
+                    continue;
+                }
+                x %= width;
+            }
+            let pixel = 1u128 << (127 - x);
+            collision |= s.screen[y] & pixel != 0;
+            s.screen[y] ^= pixel;
+        }
+        collided += collision as u8;
+    }
+    s.v[0xf] = if quirks.row_collisions && s.hires {
+        collided + clipped
+    } else {
+        (collided > 0) as u8
+    };
+}
+
+fn random_quirks(rng: &mut StdRng) -> Quirks {
+    Quirks {
+        shift: rng.gen(),
+        memory_increment_by_x: rng.gen(),
+        memory_leave_i_unchanged: rng.gen(),
+        jump: rng.gen(),
+        logic: rng.gen(),
+        wrap: rng.gen(),
+        row_collisions: rng.gen(),
+        key_wait_press: rng.gen(),
+        big_sprites: rng.gen(),
+        ..Quirks::default()
+    }
+}
+
+/// Compares the interpreter with the reference for random opcodes from `opcodes`
+fn check(seed: u64, opcodes: impl Fn(&mut StdRng) -> u16) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..CASES {
+        let opcode = opcodes(&mut rng);
+        let quirks = random_quirks(&mut rng);
+        let before = State::random(&mut rng, opcode);
+
+        let mut cpu = before.cpu(quirks);
+        cpu.step();
+        let actual = State::of(&cpu);
+        let expected = reference(&before, opcode, quirks);
+        if actual != expected {
+            let registers = |s: &State| format!("PC {:04X} I {:04X} V {:02X?}", s.pc, s.i, s.v);
+            panic!(
+                "{:04X} with {:?}\nbefore:   {}\nexpected: {}\nactual:   {}\nmemory differs: {}",
+                opcode,
+                quirks,
+                registers(&before),
+                registers(&expected),
+                registers(&actual),
+                actual.memory != expected.memory
+            );
+        }
+    }
+}
+
+/// An opcode `high` with random X and Y, and `low` as its last nibble
+fn xy(rng: &mut StdRng, high: u16, low: u16) -> u16 {
+    high << 12 | rng.gen_range(0..0x100) << 4 | low
+}
+
+/// An FXNN opcode with a random X
+fn fx(rng: &mut StdRng, nn: u16) -> u16 {
+    0xf000 | rng.gen_range(0..0x10) << 8 | nn
+}
+
+/// An opcode `high` with a random 12-bit operand
+fn operand(rng: &mut StdRng, high: u16) -> u16 {
+    high << 12 | rng.gen_range(0..0x1000)
+}
+
+#[test]
+fn arithmetic_and_flags() {
+    check(1, |rng| {
+        let low = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xe][rng.gen_range(0..9)];
+        xy(rng, 0x8, low)
+    });
+}
+
+#[test]
+fn registers() {
+    check(2, |rng| {
+        let high = [0x6, 0x7][rng.gen_range(0..2)];
+        operand(rng, high)
+    });
+}
+
+#[test]
+fn skips() {
+    check(3, |rng| match rng.gen_range(0..4) {
+        0 => operand(rng, 0x3),
+        1 => operand(rng, 0x4),
+        2 => xy(rng, 0x5, 0),
+        _ => xy(rng, 0x9, 0),
+    });
+}
+
+#[test]
+fn jumps_and_calls() {
+    check(4, |rng| {
+        let high = [0x1, 0x2, 0xb][rng.gen_range(0..3)];
+        operand(rng, high)
+    });
+}
+
+#[test]
+fn call_and_return() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..CASES {
+        let call = operand(&mut rng, 0x2) & !1;
+        let quirks = random_quirks(&mut rng);
+        let mut before = State::random(&mut rng, call);
+        before.poke(call & 0xfff, 0x00);
+        before.poke((call & 0xfff) + 1, 0xee);
+        if (call & 0xfff).abs_diff(before.pc) < 2 {
+            continue;
+        }
+
+        let mut cpu = before.cpu(quirks);
+        cpu.step();
+        cpu.step();
+        let called = reference(&before, call, quirks);
+        assert_eq!(State::of(&cpu), reference(&called, 0x00ee, quirks));
+    }
+}
+
+#[test]
+fn index() {
+    check(6, |rng| match rng.gen_range(0..3) {
+        0 => operand(rng, 0xa),
+        1 => fx(rng, 0x1e),
+        _ => fx(rng, 0x29),
+    });
+}
+
+#[test]
+fn bcd() {
+    check(7, |rng| fx(rng, 0x33));
+}
+
+#[test]
+fn load_and_store() {
+    check(8, |rng| {
+        let nn = [0x55, 0x65][rng.gen_range(0..2)];
+        fx(rng, nn)
+    });
+}
+
+#[test]
+fn screen() {
+    check(10, |rng| [0x00e0, 0x00fe, 0x00ff][rng.gen_range(0..3)]);
+}
+
+#[test]
+fn sprites() {
+    check(11, |rng| operand(rng, 0xd));
+}
+
+#[test]
+fn keys() {
+    check(12, |rng| {
+        let nn = [0x9e, 0xa1][rng.gen_range(0..2)];
+        0xe000 | rng.gen_range(0..0x10) << 8 | nn
+    });
+}
+
+#[test]
+fn timers() {
+    check(13, |rng| {
+        let nn = [0x07, 0x15, 0x18][rng.gen_range(0..3)];
+        fx(rng, nn)
+    });
+}
+
+#[test]
+fn big_font() {
+    check(14, |rng| fx(rng, 0x30));
+}
+
+#[test]
+fn wait_for_key() {
+    // FX0A takes the lowest key held, at the press or at its release
+    let mut rng = StdRng::seed_from_u64(15);
+    for _ in 0..CASES {
+        let opcode = fx(&mut rng, 0x0a);
+        let quirks = random_quirks(&mut rng);
+        let before = State::random(&mut rng, opcode);
+        let x = (opcode >> 8 & 0xf) as usize;
+        let key = before.keys.iter().position(|&down| down);
+
+        let mut cpu = before.cpu(quirks);
+        cpu.step();
+        let mut expected = before.clone();
+        expected.pc += 2;
+        match key {
+            Some(key) if quirks.key_wait_press => expected.v[x] = key as u8,
+            // waits for the release, or for a key
+            _ => (),
+        }
+        assert_eq!(
+            State::of(&cpu),
+            expected,
+            "{:04X} with {:?}",
+            opcode,
+            quirks
+        );
+
+        if key.is_some() && !quirks.key_wait_press {
+            cpu.keys_down = [false; 16];
+            cpu.step();
+            expected.keys = [false; 16];
+            expected.v[x] = key.unwrap() as u8;
+            assert_eq!(State::of(&cpu), expected, "{:04X} at the release", opcode);
+        }
+    }
+}
+
+#[test]
+fn random_numbers_are_masked() {
+    let mut rng = StdRng::seed_from_u64(9);
+    for _ in 0..CASES {
+        let opcode = operand(&mut rng, 0xc);
+        let before = State::random(&mut rng, opcode);
+        let mut cpu = before.cpu(Quirks::default());
+        cpu.step();
+
+        let x = (opcode >> 8 & 0xf) as usize;
+        let mut after = State::of(&cpu);
+        assert_eq!(after.v[x] & !(opcode as u8), 0, "{:04X}", opcode);
+        after.v[x] = before.v[x];
+        assert_eq!(after.pc, before.pc + 2);
+        after.pc = before.pc;
+        assert_eq!(after, before);
+    }
+}
+
+#[test]
+fn self_modifying_code() {
+    // calls a subroutine that adds 1 to V2, changes it to add 5, and calls it again
+    let rom = [
+        0x22, 0x10, 0x60, 0x05, 0xa2, 0x11, 0xf0, 0x55, 0x22, 0x10, 0x12, 0x0a, 0, 0, 0, 0, 0x72,
+        0x01, 0x00, 0xee,
+    ];
+    let mut cpu = Cpu::with_profile(CHIP_8);
+    cpu.bus.load_rom(&rom);
+    for _ in 0..8 {
+        cpu.step();
+    }
+    assert_eq!(cpu.v()[2], 6);
+}
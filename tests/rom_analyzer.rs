@@ -0,0 +1,152 @@
+//! Guessing the platform of ROMs that aren't in the database, and how sure
+//! the guess is.
+
+use chippie::rom_analyzer::{analyze, Analysis};
+
+/// Enough plain CHIP-8 instructions for the guess not to be a weak one
+const FILLER: [u16; 16] = [0x7001; 16];
+
+/// The program `ops` at 200, ending in a jump to itself
+fn analyze_ops(ops: &[u16]) -> Analysis {
+    let mut rom: Vec<u8> = ops.iter().flat_map(|op| op.to_be_bytes()).collect();
+    let end = 0x200 + rom.len() as u16;
+    rom.extend((0x1000 | end).to_be_bytes());
+    analyze(&rom, 0x200)
+}
+
+fn assert_confidence(analysis: &Analysis, expected: f32) {
+    assert!(
+        (analysis.confidence - expected).abs() < 1e-5,
+        "the confidence is {}, not {}",
+        analysis.confidence,
+        expected
+    );
+}
+
+#[test]
+fn plain_chip8_is_a_weak_guess() {
+    let analysis = analyze_ops(&FILLER);
+    assert_eq!(analysis.platform, "originalChip8");
+    assert_confidence(&analysis, 0.4);
+}
+
+#[test]
+fn super_chip_instructions_make_it_super_chip() {
+    // 00FF, DXY0 and FX30
+    let mut ops = vec![0x00FF, 0xD010, 0xF030];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "superchip");
+    assert_confidence(&analysis, 0.9);
+    assert!(analysis.quirks.big_sprites);
+}
+
+#[test]
+fn xo_chip_instructions_win_over_super_chip() {
+    // F000 NNNN and 5XY2 along with 00FF
+    let mut ops = vec![0x00FF, 0xF000, 0x1234, 0x5012];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "xochip");
+    assert_confidence(&analysis, 0.8);
+}
+
+#[test]
+fn machine_code_makes_it_a_vip_hybrid() {
+    let mut ops = vec![0x0300];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "hybridVIP");
+    assert_confidence(&analysis, 0.7);
+}
+
+#[test]
+fn confidence_grows_with_the_evidence_up_to_a_limit() {
+    let mut ops = vec![0x00FF; 10];
+    ops.extend(FILLER);
+    assert_confidence(&analyze_ops(&ops), 0.95);
+}
+
+#[test]
+fn little_code_halves_the_confidence() {
+    let analysis = analyze_ops(&[0x00FF]);
+    assert_eq!(analysis.platform, "superchip");
+    assert_confidence(&analysis, 0.35);
+}
+
+#[test]
+fn unreachable_code_is_ignored() {
+    // jumps over the 00FF
+    let mut ops = vec![0x1204, 0x00FF];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "originalChip8");
+}
+
+#[test]
+fn skips_reach_both_ways() {
+    let mut ops = vec![0x3000, 0x00FF];
+    ops.extend(FILLER);
+    assert_eq!(analyze_ops(&ops).platform, "superchip");
+}
+
+#[test]
+fn shifts_from_vy_back_the_original_shift() {
+    let mut ops = vec![0x8126];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert!(!analysis.quirks.shift);
+    assert_confidence(&analysis, 0.4 + 0.6 * 0.3);
+
+    // SUPER-CHIP shifts in place, so the guess is less sure of its quirks
+    let mut ops = vec![0x8126, 0x00FF];
+    ops.extend(FILLER);
+    let analysis = analyze_ops(&ops);
+    assert!(!analysis.quirks.shift);
+    assert_confidence(&analysis, 0.7 * 0.8);
+}
+
+#[test]
+fn jumps_are_followed_from_the_start_address() {
+    // at 600, a jump over data to 00FF
+    let rom: Vec<u8> = [0x1604, 0x0000, 0x00FF, 0x1606]
+        .iter()
+        .flat_map(|op: &u16| op.to_be_bytes())
+        .collect();
+    assert_eq!(analyze(&rom, 0x600).platform, "superchip");
+    // from 200, the jump leads out of the program
+    assert_eq!(analyze(&rom, 0x200).platform, "originalChip8");
+}
+
+#[test]
+fn jumps_offset_by_the_vx_just_set_call_for_the_jump_quirk() {
+    // V1 = 4, B104
+    let mut ops = FILLER.to_vec();
+    ops.extend([0x6104, 0xB104]);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "originalChip8");
+    assert!(analysis.quirks.jump);
+    assert_confidence(&analysis, 0.4 * 0.8);
+}
+
+#[test]
+fn jumps_offset_by_the_v0_just_set_go_without_it() {
+    // V0 = 4, B300 along with 00FF
+    let mut ops = vec![0x00FF];
+    ops.extend(FILLER);
+    ops.extend([0x6004, 0xB300]);
+    let analysis = analyze_ops(&ops);
+    assert_eq!(analysis.platform, "superchip");
+    assert!(!analysis.quirks.jump);
+    assert_confidence(&analysis, 0.7 * 0.8);
+}
+
+#[test]
+fn jumps_with_nothing_set_before_tell_nothing() {
+    // the filler sets V0, so the screen is cleared in between
+    let mut ops = FILLER.to_vec();
+    ops.extend([0x00E0, 0xB300]);
+    let analysis = analyze_ops(&ops);
+    assert!(!analysis.quirks.jump);
+    assert_confidence(&analysis, 0.4);
+}
@@ -0,0 +1,173 @@
+//! Scanning a ROM folder: matching against the database, duplicates,
+//! unknown files and refreshing what changed.
+
+use chippie::rom_scanner::{RomIndex, INDEX_FILE_NAME};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A known ROM: Timendus' CHIP-8 logo test
+fn logo() -> Vec<u8> {
+    fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/roms/tests/1-chip8-logo.ch8"
+    ))
+    .unwrap()
+}
+
+/// An empty folder of its own for each test
+fn folder(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chippie-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, path: &str, data: &[u8]) {
+    let file = dir.join(path);
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    fs::write(file, data).unwrap();
+}
+
+fn paths(index: &RomIndex) -> Vec<&str> {
+    index
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect()
+}
+
+#[test]
+fn known_roms_match_and_the_rest_is_unknown() {
+    let dir = folder("match");
+    write(&dir, "tests/logo.ch8", &logo());
+    write(&dir, "mine.ch8", &[0x12, 0x00, 0xAB]);
+
+    let index = RomIndex::open(&dir).unwrap();
+    assert_eq!(paths(&index), ["mine.ch8", "tests/logo.ch8"]);
+    let matched = index.matched();
+    assert_eq!(matched.len(), 1);
+    assert_eq!(matched[0].files[0].path, "tests/logo.ch8");
+    let unknown = index.unknown();
+    assert_eq!(unknown.len(), 1);
+    assert_eq!(unknown[0].path, "mine.ch8");
+    assert!(index.duplicates().is_empty());
+}
+
+#[test]
+fn copies_are_duplicates_of_one_title() {
+    let dir = folder("duplicates");
+    write(&dir, "a.ch8", &logo());
+    write(&dir, "b/a copy.ch8", &logo());
+
+    let index = RomIndex::open(&dir).unwrap();
+    let matched = index.matched();
+    assert_eq!(matched.len(), 1);
+    assert_eq!(matched[0].files.len(), 2);
+    let duplicates = index.duplicates();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].files, ["a.ch8", "b/a copy.ch8"]);
+}
+
+#[test]
+fn files_too_big_for_any_machine_are_left_out() {
+    let dir = folder("too-big");
+    write(&dir, "big.bin", &vec![0; 0x10000]);
+    write(&dir, "small.ch8", &[0; 0x100]);
+
+    let index = RomIndex::open(&dir).unwrap();
+    assert_eq!(paths(&index), ["small.ch8"]);
+}
+
+#[test]
+fn refresh_hashes_only_what_changed() {
+    let dir = folder("refresh");
+    write(&dir, "same.ch8", &[1, 2, 3]);
+    write(&dir, "changed.ch8", &[1, 2, 3]);
+    write(&dir, "deleted.ch8", &[1, 2, 3]);
+
+    let mut index = RomIndex::open(&dir).unwrap();
+    // a hash that can only have come from the index, not from the file
+    for entry in &mut index.entries {
+        entry.sha1 = "cached".to_string();
+    }
+    write(&dir, "changed.ch8", &[1, 2, 3, 4]);
+    fs::remove_file(dir.join("deleted.ch8")).unwrap();
+    write(&dir, "new.ch8", &[1, 2, 3]);
+    index.refresh().unwrap();
+
+    assert_eq!(paths(&index), ["changed.ch8", "new.ch8", "same.ch8"]);
+    let sha1 = |path: &str| {
+        let entry = index.entries.iter().find(|entry| entry.path == path);
+        entry.unwrap().sha1.clone()
+    };
+    assert_eq!(sha1("same.ch8"), "cached");
+    assert_ne!(sha1("changed.ch8"), "cached");
+    assert_ne!(sha1("new.ch8"), "cached");
+}
+
+#[test]
+fn the_cache_is_reused_when_reopened() {
+    let dir = folder("reopen");
+    write(&dir, "mine.ch8", &[1, 2, 3]);
+
+    let mut index = RomIndex::open(&dir).unwrap();
+    index.entries[0].sha1 = "cached".to_string();
+    index.save_cache().unwrap();
+
+    let index = RomIndex::open(&dir).unwrap();
+    assert_eq!(index.entries[0].sha1, "cached");
+}
+
+#[test]
+fn a_failed_refresh_keeps_the_index() {
+    let dir = folder("failed");
+    write(&dir, "mine.ch8", &[1, 2, 3]);
+
+    let mut index = RomIndex::open(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(index.refresh().is_err());
+    assert_eq!(paths(&index), ["mine.ch8"]);
+}
+
+#[test]
+fn a_folder_without_room_for_the_cache_is_scanned_all_the_same() {
+    let dir = folder("no-cache");
+    write(&dir, "mine.ch8", &[1, 2, 3]);
+    // the cache file can't be written where a directory is in the way
+    fs::create_dir(dir.join(INDEX_FILE_NAME)).unwrap();
+
+    let index = RomIndex::open(&dir).unwrap();
+    assert_eq!(paths(&index), ["mine.ch8"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_folders_are_left_out() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = folder("unreadable");
+    write(&dir, "mine.ch8", &[1, 2, 3]);
+    write(&dir, "locked/theirs.ch8", &[4, 5, 6]);
+    let locked = dir.join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    let readable = fs::read_dir(&locked).is_ok();
+
+    let index = RomIndex::open(&dir);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    if readable {
+        eprintln!("permissions don't keep this user out, the test is not run");
+        return;
+    }
+    assert_eq!(paths(&index.unwrap()), ["mine.ch8"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loops_are_followed_once() {
+    let dir = folder("symlinks");
+    write(&dir, "games/mine.ch8", &[1, 2, 3]);
+    std::os::unix::fs::symlink(&dir, dir.join("games/up")).unwrap();
+
+    let index = RomIndex::open(&dir).unwrap();
+    assert_eq!(paths(&index), ["games/mine.ch8"]);
+}
@@ -0,0 +1,32 @@
+//! Shadow runs: two machines in lockstep, and where they first part ways.
+
+use chippie::chip8::profile::CHIP_8;
+use chippie::chip8::shadow::Shadow;
+use chippie::chip8::Cpu;
+
+#[test]
+fn the_divergence_is_found_after_many_frames() {
+    // wait a second on the delay timer, then shift V2 into V1
+    let rom = [
+        0x6A, 0x3C, 0xFA, 0x15, 0xFA, 0x07, 0x3A, 0x00, 0x12, 0x04, 0x61, 0x01, 0x62, 0x04, 0x81,
+        0x26, 0x12, 0x10,
+    ];
+    let cpu = |shift| {
+        let mut cpu = Cpu::with_profile(CHIP_8);
+        cpu.quirks.shift = shift;
+        cpu.bus.load_rom(&rom);
+        cpu
+    };
+    let mut main = cpu(false);
+    let mut shadow = Shadow::new(&mut main, cpu(true), 0);
+    for _ in 0..100 {
+        shadow.run_frame(&mut main, 100);
+    }
+
+    let divergence = shadow
+        .divergence
+        .expect("the shift quirk made no difference");
+    assert_eq!(divergence.frame, 60);
+    assert_eq!((divergence.pc, divergence.opcode), (0x020E, 0x8126));
+    assert_eq!(divergence.detail, "V1 is 02 vs 00");
+}
@@ -0,0 +1,103 @@
+//! Drawing sprites: DXY0 on each platform, and sprites that cross the edges
+//! of the screen, clipped or wrapped.
+
+use chippie::chip8::gpu::HIRES;
+use chippie::chip8::quirks::Quirks;
+use chippie::chip8::Cpu;
+
+/// Draws a sprite of N rows at (x, y) from a sprite that is all pixels on,
+/// then loops; the sprite starts at 020A
+fn draw(quirks: Quirks, hires: bool, x: u8, y: u8, n: u8) -> Cpu {
+    let mut rom = vec![0x60, x, 0x61, y, 0xA2, 0x0A, 0xD0, 0x10 | n, 0x12, 0x08];
+    rom.extend([0xFF; 32]);
+    let mut cpu = Cpu::new();
+    cpu.quirks = quirks;
+    if hires {
+        cpu.bus.gpu.set_size(HIRES.0, HIRES.1);
+    }
+    cpu.bus.load_rom(&rom);
+    cpu.ticks(10);
+    cpu
+}
+
+fn lit(cpu: &Cpu) -> u32 {
+    cpu.bus.gpu.buffer.iter().map(|row| row.count_ones()).sum()
+}
+
+#[test]
+fn dxy0_draws_nothing_on_chip8() {
+    let cpu = draw(Quirks::default(), false, 0, 0, 0);
+    assert_eq!(lit(&cpu), 0);
+    assert_eq!(cpu.v()[0xf], 0);
+}
+
+#[test]
+fn dxy0_draws_16x16_with_the_quirk() {
+    let quirks = Quirks {
+        big_sprites: true,
+        ..Quirks::default()
+    };
+    assert_eq!(lit(&draw(quirks, false, 0, 0, 0)), 16 * 16);
+}
+
+#[test]
+fn dxy0_draws_16x16_in_hires() {
+    assert_eq!(lit(&draw(Quirks::default(), true, 0, 0, 0)), 16 * 16);
+}
+
+#[test]
+fn sprites_are_clipped_at_the_edges() {
+    let cpu = draw(Quirks::default(), false, 60, 30, 4);
+    let gpu = &cpu.bus.gpu;
+    assert_eq!(lit(&cpu), 4 * 2);
+    assert!(gpu.pixel(60, 30) && gpu.pixel(63, 31));
+    assert!(!gpu.pixel(0, 0));
+    assert_eq!(cpu.v()[0xf], 0);
+}
+
+#[test]
+fn sprites_wrap_around_the_edges_with_the_quirk() {
+    let quirks = Quirks {
+        wrap: true,
+        ..Quirks::default()
+    };
+    let cpu = draw(quirks, false, 60, 30, 4);
+    let gpu = &cpu.bus.gpu;
+    assert_eq!(lit(&cpu), 8 * 4);
+    assert!(gpu.pixel(60, 30) && gpu.pixel(63, 31));
+    assert!(gpu.pixel(0, 0) && gpu.pixel(3, 1));
+    assert!(!gpu.pixel(4, 0) && !gpu.pixel(0, 2));
+}
+
+#[test]
+fn big_sprites_are_clipped_and_wrapped_too() {
+    let cpu = draw(Quirks::default(), true, 120, 56, 0);
+    assert_eq!(lit(&cpu), 8 * 8);
+
+    let quirks = Quirks {
+        wrap: true,
+        ..Quirks::default()
+    };
+    let cpu = draw(quirks, true, 120, 56, 0);
+    assert_eq!(lit(&cpu), 16 * 16);
+    assert!(cpu.bus.gpu.pixel(7, 7));
+    assert!(!cpu.bus.gpu.pixel(8, 8));
+}
+
+#[test]
+fn the_start_position_always_wraps() {
+    let cpu = draw(Quirks::default(), false, 64 + 2, 32 + 1, 1);
+    assert!(cpu.bus.gpu.pixel(2, 1) && cpu.bus.gpu.pixel(9, 1));
+    assert_eq!(lit(&cpu), 8);
+}
+
+#[test]
+fn hires_row_collisions_count_the_clipped_rows() {
+    let quirks = Quirks {
+        row_collisions: true,
+        ..Quirks::default()
+    };
+    // 12 of the 16 rows fall off the bottom
+    let cpu = draw(quirks, true, 0, 60, 0);
+    assert_eq!(cpu.v()[0xf], 12);
+}
@@ -0,0 +1,101 @@
+//! The stack's depth, and what calls do when it is full.
+
+use chippie::chip8::cpu::StackOverflow;
+use chippie::chip8::profile::{MachineProfile, CHIP_8, ETI_660, VIP_HYBRID, XO_CHIP};
+use chippie::chip8::Cpu;
+
+/// 2202 calls itself forever
+const RECURSION: [u8; 4] = [0x00, 0xe0, 0x22, 0x02];
+
+fn run(overflow: StackOverflow, rom: &[u8], ticks: u16) -> Cpu {
+    run_on(CHIP_8, overflow, rom, ticks)
+}
+
+fn run_on(profile: MachineProfile, overflow: StackOverflow, rom: &[u8], ticks: u16) -> Cpu {
+    let mut cpu = Cpu::with_profile(profile);
+    cpu.stack_overflow = overflow;
+    cpu.bus.load_rom(rom);
+    cpu.ticks(ticks);
+    cpu
+}
+
+#[test]
+fn depth_comes_from_the_profile() {
+    assert_eq!(Cpu::with_profile(CHIP_8).stack_depth, 16);
+    assert_eq!(Cpu::with_profile(VIP_HYBRID).stack_depth, 12);
+}
+
+#[test]
+fn wrap_drops_the_oldest_call() {
+    let mut cpu = Cpu::with_profile(CHIP_8);
+    cpu.stack_depth = 4;
+    cpu.bus.load_rom(&RECURSION);
+    cpu.ticks(100);
+    assert_eq!(cpu.stack(), vec![0x204; 4]);
+    assert!(cpu.error().is_none());
+}
+
+#[test]
+fn error_stops_at_the_call() {
+    let cpu = run(StackOverflow::Error, &RECURSION, 100);
+    assert_eq!(cpu.stack().len(), 16);
+    assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.error(), Some("Stack overflow at 0202"));
+}
+
+#[test]
+fn error_stops_at_a_return_without_a_call() {
+    let cpu = run(StackOverflow::Error, &[0x00, 0xee], 10);
+    assert_eq!(cpu.pc, 0x200);
+    assert!(cpu.error().is_some());
+}
+
+#[test]
+fn memory_keeps_the_stack_where_the_vip_does() {
+    // call 206, which returns
+    let cpu = run_on(
+        VIP_HYBRID,
+        StackOverflow::Memory,
+        &[0x22, 0x06, 0x12, 0x02, 0, 0, 0x12, 0x06],
+        3,
+    );
+    assert_eq!(cpu.stack(), vec![0x202]);
+    assert_eq!(cpu.bus.memory()[0xece..0xed0], [0x02, 0x02]);
+
+    // deep calls go on below 0EA0
+    let cpu = run_on(VIP_HYBRID, StackOverflow::Memory, &RECURSION, 100);
+    assert_eq!(cpu.stack().len(), 99);
+    assert_eq!(cpu.bus.memory()[0xe0a..0xe0c], [0x02, 0x04]);
+}
+
+#[test]
+fn memory_lets_programs_change_return_addresses() {
+    // call 206, which writes 0220 over its return address and returns
+    let rom = [
+        0x22, 0x06, 0x12, 0x02, 0, 0, // main
+        0x60, 0x02, 0x61, 0x20, 0xae, 0xce, 0xf1, 0x55, 0x00, 0xee, // 206
+    ];
+    let cpu = run_on(VIP_HYBRID, StackOverflow::Memory, &rom, 6);
+    assert_eq!(cpu.pc, 0x220);
+    assert!(cpu.stack().is_empty());
+}
+
+#[test]
+fn memory_keeps_the_stack_below_the_program_elsewhere() {
+    for (profile, top) in [(CHIP_8, 0x1ff), (XO_CHIP, 0x1ff), (ETI_660, 0x5ff)] {
+        // call the jump to itself 6 bytes on
+        let start = profile.program_start;
+        let call = (0x2000 | (start + 6)).to_be_bytes();
+        let jump = (0x1000 | (start + 6)).to_be_bytes();
+        let rom = [call[0], call[1], 0, 0, 0, 0, jump[0], jump[1]];
+        let cpu = run_on(profile, StackOverflow::Memory, &rom, 3);
+        assert_eq!(cpu.stack(), vec![start + 2], "{}", profile.name);
+        let [high, low] = (start + 2).to_be_bytes();
+        assert_eq!(
+            cpu.bus.memory()[top - 1..=top],
+            [high, low],
+            "{}",
+            profile.name
+        );
+    }
+}
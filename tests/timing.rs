@@ -0,0 +1,40 @@
+//! COSMAC VIP cycle timing against the plain instruction count.
+
+use chippie::chip8::timing::Timing;
+use chippie::chip8::Cpu;
+
+/// Draws a sprite and counts in V0, forever
+const DRAW_LOOP: [u8; 6] = [0xD0, 0x01, 0x70, 0x01, 0x12, 0x00];
+
+fn draw_loop(timing: Timing, vblank: bool) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.timing = timing;
+    cpu.quirks.vblank = vblank;
+    cpu.bus.load_rom(&DRAW_LOOP);
+    for _ in 0..10 {
+        cpu.ticks(30);
+    }
+    cpu
+}
+
+#[test]
+fn vip_timing_draws_once_a_frame_without_the_vblank_quirk() {
+    // the first frame ends at the first sprite
+    assert_eq!(draw_loop(Timing::VipCycles, false).v()[0], 9);
+}
+
+#[test]
+fn vip_timing_draws_once_a_frame_with_the_vblank_quirk() {
+    assert_eq!(draw_loop(Timing::VipCycles, true).v()[0], 9);
+}
+
+#[test]
+fn the_vblank_quirk_draws_once_a_frame() {
+    assert_eq!(draw_loop(Timing::Instructions, true).v()[0], 9);
+}
+
+#[test]
+fn without_the_vblank_quirk_sprites_are_drawn_right_away() {
+    // 30 instructions a frame, three to a round of the loop
+    assert_eq!(draw_loop(Timing::Instructions, false).v()[0], 100);
+}
@@ -0,0 +1,100 @@
+//! The emulated COSMAC VIP: its display timing, and the bundled test ROMs
+//! run on it and on the interpreter set up like it, which should draw the
+//! same screen.
+//!
+//! The test ROMs need an image of the original CHIP-8 interpreter, which
+//! can't be bundled; set `CHIPPIE_VIP_INTERPRETER` to its path to run them.
+
+use chippie::chip8::profile::VIP_HYBRID;
+use chippie::chip8::quirks::Quirks;
+use chippie::chip8::timing::Timing;
+use chippie::chip8::vip::Vip;
+use chippie::chip8::{Cpu, Machine};
+
+/// Enough for the test ROMs to finish drawing, and then some
+const FRAMES: usize = 180;
+
+fn interpreter() -> Option<Vec<u8>> {
+    let path = std::env::var_os("CHIPPIE_VIP_INTERPRETER")?;
+    Some(std::fs::read(path).expect("can't read CHIPPIE_VIP_INTERPRETER"))
+}
+
+/// The interpreter as the VIP runs it: its quirks, timing and memory
+fn vip_like_cpu(rom: &[u8]) -> Cpu {
+    let mut cpu = Cpu::with_profile(VIP_HYBRID);
+    cpu.timing = Timing::VipCycles;
+    cpu.quirks = Quirks {
+        jump: false,
+        logic: true,
+        vblank: true,
+        ..Quirks::default()
+    };
+    cpu.bus.load_rom(rom);
+    cpu
+}
+
+fn check(name: &str) {
+    let Some(interpreter) = interpreter() else {
+        eprintln!("CHIPPIE_VIP_INTERPRETER isn't set, {} is not run", name);
+        return;
+    };
+    let path = format!("{}/roms/tests/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let rom = std::fs::read(path).unwrap();
+
+    let mut vip = Vip::new(&interpreter, &rom);
+    let mut cpu = vip_like_cpu(&rom);
+    for _ in 0..FRAMES {
+        vip.run_frame(0);
+        Machine::run_frame(&mut cpu, 0);
+    }
+    assert_eq!(cpu.error(), None, "{}", name);
+    assert!(
+        vip.gpu().buffer == cpu.gpu().buffer,
+        "{}: the screens differ",
+        name
+    );
+}
+
+#[test]
+fn ef1_is_set_for_the_last_display_lines_only() {
+    // machine code in place of the interpreter: with interrupts off, switch
+    // the display on, count in R4 while EF1 is set, and set Q if that was
+    // short
+    #[rustfmt::skip]
+    let code = [
+        0xF8, 0x08, 0xA3, // LDI 08; PLO 3
+        0x71, 0x03,       // DIS with X = 0 and P = 3, on at 0008
+        0x00, 0x00, 0x00,
+        0xE1, 0x69,       // SEX 1; INP 1
+        0x3C, 0x0A,       // BN1 0A
+        0x14, 0x34, 0x0C, // INC 4; B1 0C
+        0x94, 0x3A, 0x13, // GHI 4; BNZ 13
+        0x7B,             // SEQ
+        0x30, 0x13,       // BR 13
+    ];
+    let mut vip = Vip::new(&code, &[]);
+    vip.run_frame(0);
+    assert!(!vip.is_beeping());
+    vip.run_frame(0);
+    assert!(vip.is_beeping());
+}
+
+#[test]
+fn chip8_logo() {
+    check("1-chip8-logo.ch8");
+}
+
+#[test]
+fn ibm_logo() {
+    check("2-ibm-logo.ch8");
+}
+
+#[test]
+fn corax_plus() {
+    check("3-corax+.ch8");
+}
+
+#[test]
+fn flags() {
+    check("4-flags.ch8");
+}